@@ -1,10 +1,13 @@
 # flake8: noqa: PYI021
-def analyze_dockerfile(body: str) -> Analysis:
+def analyze_dockerfile(
+    body: str, options: Optional[AnalysisOptions] = None
+) -> Analysis:
     """
     Analyzes a Dockerfile and returns detailed analysis information.
 
     Args:
         dockerfile_content (str): The content of the Dockerfile to analyze
+        options (AnalysisOptions | None): Options controlling the analysis
 
     Returns:
         Analysis: A comprehensive analysis object containing information about:
@@ -14,9 +17,12 @@ def analyze_dockerfile(body: str) -> Analysis:
             - Instructions statistics
             - Environment variables, labels, and arguments
             - Exposed ports
+            - Lint findings
 
     Raises:
-        ValueError: If the dockerfile content is empty or invalid
+        EmptyDockerfileError: If the dockerfile content is empty
+        DockerfileParseError: If the dockerfile content is invalid
+        DuplicateStageError: If two stages share a name
 
     Example:
         >>> analysis = analyze_dockerfile('FROM ubuntu:20.04\nRUN echo hello')
@@ -25,30 +31,399 @@ def analyze_dockerfile(body: str) -> Analysis:
 
     """
 
+def analyze_dockerfile_cached(
+    body: str, options: Optional[AnalysisOptions] = None
+) -> Analysis:
+    """
+    Analyzes a Dockerfile like analyze_dockerfile, reusing earlier results.
+
+    Analyses are kept in a process-wide least-recently-used cache keyed on the
+    Dockerfile content and options. Each call returns a copy of the cached
+    Analysis, so modifying the result does not affect later calls.
+
+    Args:
+        body (str): The content of the Dockerfile to analyze
+        options (AnalysisOptions | None): Options controlling the analysis
+
+    Returns:
+        Analysis: The same value analyze_dockerfile would return
+
+    Raises:
+        EmptyDockerfileError: If the dockerfile content is empty
+        DockerfileParseError: If the dockerfile content is invalid
+    """
+
+def set_analysis_cache_capacity(capacity: int) -> None:
+    """
+    Sets how many analyses analyze_dockerfile_cached keeps.
+
+    The least recently used entries are dropped when the cache shrinks. A
+    capacity of 0 disables caching. Defaults to 128.
+
+    Args:
+        capacity (int): The maximum number of cached analyses
+    """
+
+def analyze_dockerfile_target(
+    body: str, target: str, options: Optional[AnalysisOptions] = None
+) -> Analysis:
+    """
+    Analyzes the part of a Dockerfile built by docker build --target.
+
+    Only the target stage and the stages it transitively builds on or copies
+    from are analyzed. Line numbers refer to the original Dockerfile, while
+    stage indices refer to the stages that remain. file_metrics counts the
+    lines of the whole Dockerfile.
+
+    Args:
+        body (str): The content of the Dockerfile to analyze
+        target (str): The name of the target stage
+        options (AnalysisOptions | None): Options controlling the analysis
+
+    Returns:
+        Analysis: The analysis of the target stage and its dependencies
+
+    Raises:
+        EmptyDockerfileError: If the dockerfile content is empty
+        DockerfileParseError: If the dockerfile content is invalid
+        TargetNotFoundError: If the Dockerfile has no stage named target
+    """
+
+def analyze_dockerfile_bytes(
+    data: bytes, options: Optional[AnalysisOptions] = None
+) -> Analysis:
+    """
+    Analyzes a Dockerfile given as bytes of unknown encoding.
+
+    UTF-8 content is analyzed as is, with any byte order mark removed. Other
+    content is decoded with invalid bytes replaced by U+FFFD, and an
+    invalid-utf8 warning finding gives the line of the first one.
+
+    Args:
+        data (bytes): The raw content of the Dockerfile to analyze
+        options (AnalysisOptions | None): Options controlling the analysis
+
+    Returns:
+        Analysis: The analysis of the decoded Dockerfile
+
+    Raises:
+        EmptyDockerfileError: If the dockerfile content is empty
+        DockerfileParseError: If the dockerfile content is invalid
+    """
+
+def analyze_dockerfiles(
+    bodies: List[str],
+    options: Optional[AnalysisOptions] = None,
+    parallel: bool = False,
+) -> List[Analysis]:
+    """
+    Analyzes a list of Dockerfiles.
+
+    The GIL is released while the Dockerfiles are analyzed, and with
+    parallel=True they are analyzed across multiple threads.
+
+    Args:
+        bodies (list[str]): The contents of the Dockerfiles to analyze
+        options (AnalysisOptions | None): Options applied to every Dockerfile
+        parallel (bool): Whether to analyze the Dockerfiles in parallel
+
+    Returns:
+        list[Analysis]: One analysis per Dockerfile, in input order
+
+    Raises:
+        DockerfileError: If any Dockerfile is empty or invalid; the exception
+            has the subclass matching the failure and the message gives the
+            index of the first one that failed
+    """
+
+def analyze_multistage_only(body: str) -> MultistageAnalysis:
+    """
+    Analyzes only the multistage structure of a Dockerfile.
+
+    This is a faster alternative to analyze_dockerfile when only stage
+    relationships are needed; instructions, environment variables, labels,
+    ports and lint findings are not extracted.
+
+    Args:
+        body (str): The content of the Dockerfile to analyze
+
+    Returns:
+        MultistageAnalysis: The same value as Analysis.multistage_analysis
+
+    Raises:
+        EmptyDockerfileError: If the dockerfile content is empty
+        DockerfileParseError: If the dockerfile content is invalid
+    """
+
+def analysis_from_json(json: str) -> Analysis:
+    """
+    Rebuilds an Analysis from the JSON produced by Analysis.to_json.
+
+    Args:
+        json (str): A JSON string previously returned by Analysis.to_json
+
+    Returns:
+        Analysis: An analysis equal to the one that was serialized
+
+    Raises:
+        ValueError: If the JSON is malformed or does not describe an Analysis
+    """
+
 from typing import Dict, List, Optional, Any
 
+class DockerfileError(ValueError):
+    """Base class for errors raised while analyzing a Dockerfile."""
+
+class EmptyDockerfileError(DockerfileError):
+    """Raised when a Dockerfile is empty or contains only comments."""
+
+class DockerfileParseError(DockerfileError):
+    """Raised when a Dockerfile cannot be parsed."""
+
+class DuplicateStageError(DockerfileParseError):
+    """Raised when two stages of a Dockerfile share a name."""
+
+class TargetNotFoundError(DockerfileError):
+    """Raised when the requested target stage does not exist."""
+
 class Analysis:
     num_stages: int
+    escape_char: str
+    syntax_frontend: Optional[str]
+    skipped_checks: List[str]
+    is_windows: bool
+    final_base_chain: List[str]
     images: List[Image]
+    distinct_base_images: int
+    reused_base_images: List[str]
+    digest_pinned_images: List[str]
+    registries: List[str]
     stage_names: List[str]
+    removable_stages: List[str]
+    stages: List[StageAnalysis]
     copy_from_stages: List[str]
+    copy_from_images: List[Image]
     add_from_stages: List[str]
     multistage_analysis: MultistageAnalysis
     exposed_ports: List[str]
+    parsed_ports: List[ExposedPort]
     instructions: InstructionStats
+    flags_used: Dict[str, int]
+    file_metrics: FileMetrics
+    per_stage_distinct_instruction_kinds: List[int]
+    ordered_instructions: List[InstructionEntry]
+    copy_operations: List[CopyOperation]
+    copy_optimization_suggestions: List[str]
+    run_operations: List[RunOperation]
+    has_offline_runs: bool
+    uses_copy_link: bool
+    created_users: List[str]
     args: Dict[str, Optional[str]]
+    global_args: Dict[str, Optional[str]]
+    arg_usages: Dict[str, List[str]]
+    required_build_args: List[str]
     labels: Dict[str, str]
     env_vars: Dict[str, str]
+    env_count: int
+    arg_count: int
+    label_count: int
+    effective_env: Dict[str, str]
+    path_modifications: List[str]
+    stop_signal: Optional[str]
+    maintainer: Optional[str]
+    findings: List[Finding]
+    quality_score: int
+    quality_deductions: Dict[str, int]
+
+    def to_dict(self) -> Dict[str, Any]: ...
+    def __repr__(self) -> str: ...
+    def __eq__(self, other: object) -> bool: ...
+    def __hash__(self) -> int: ...
+    def build_order(self) -> List[int]:
+        """
+        Returns stage indices with every stage after the stages it depends on.
+
+        Raises:
+            ValueError: If the stage dependencies form a cycle
+        """
+        ...
+    def to_json(self) -> str:
+        """
+        Serializes the analysis to JSON; see analysis_from_json.
+        """
+        ...
+    def to_dot(self) -> str:
+        """
+        Renders the stage graph in Graphviz DOT format. Edges are labeled
+        'base', 'copy' or 'add'; unnamed stages are labeled 'stage N'.
+        """
+        ...
+    def to_report(self) -> str:
+        """
+        Renders a plain-text report of the stages, images, exposed ports and
+        findings, meant for printing to a terminal. str(analysis) returns
+        the same report.
+        """
+        ...
+    def external_images(self) -> List[Image]:
+        """
+        Returns the entries of images that are not stage names.
+        """
+        ...
+    def stage_base_references(self) -> List[Image]:
+        """
+        Returns the entries of images that refer to earlier stages, such as
+        'base' in 'FROM base'.
+        """
+        ...
+    def port_numbers(self) -> List[int]:
+        """
+        Returns the distinct exposed port numbers in ascending order,
+        ignoring the protocol.
+        """
+        ...
+    def exposed_protocols(self) -> List[str]:
+        """
+        Returns the distinct protocols of the exposed ports, such as 'tcp'
+        and 'udp', in alphabetical order.
+        """
+        ...
+    def oci_label_report(self) -> OciLabelReport:
+        """
+        Reports which recommended org.opencontainers.image.* labels are set.
+        """
+        ...
+    def summary(self) -> Summary:
+        """
+        Returns the headline counts of the analysis.
+        """
+        ...
+    def __getstate__(self) -> bytes: ...
+    def __setstate__(self, state: bytes) -> None: ...
+
+class CopyOperation:
+    instruction: str
+    sources: List[str]
+    destination: str
+    stage_index: int
+    line: int
+    chmod: Optional[str]
+    chmod_octal: Optional[int]
+    from_source: Optional[str]
+    checksum: Optional[str]
+    link: bool
+
+    def to_dict(self) -> Dict[str, Any]: ...
+    def __repr__(self) -> str: ...
+
+class RunOperation:
+    command: str
+    network: Optional[str]
+    shell: Optional[List[str]]
+    stage_index: int
+    line: int
+
+    def to_dict(self) -> Dict[str, Any]: ...
+    def __repr__(self) -> str: ...
+
+class Finding:
+    rule_id: str
+    severity: str
+    message: str
+    line: Optional[int]
+
+    def to_dict(self) -> Dict[str, Any]: ...
+    def __repr__(self) -> str: ...
+
+class OciLabelReport:
+    present: List[str]
+    missing: List[str]
+
+    def to_dict(self) -> Dict[str, Any]: ...
+    def __repr__(self) -> str: ...
+
+class Summary:
+    num_stages: int
+    is_multistage: bool
+    num_images: int
+    num_exposed_ports: int
+    num_findings: int
+    quality_score: int
 
     def to_dict(self) -> Dict[str, Any]: ...
     def __repr__(self) -> str: ...
+    def __eq__(self, other: object) -> bool: ...
+    def __hash__(self) -> int: ...
+
+class AnalysisOptions:
+    exclude_stage_references_from_images: bool
+    require_healthcheck: bool
+    instruction_counts_per_stage: bool
+    require_tag_with_digest: bool
+    max_instructions: int
+    run_lints: bool
+    floating_tags: List[str]
+    validate_oci_labels: bool
+    require_explicit_registry: bool
+    allowed_registries: List[str]
+    honor_check_directive: bool
+    preserve_stage_case: bool
+    max_run_length: Optional[int]
+    max_run_commands: Optional[int]
+
+    def __init__(
+        self,
+        exclude_stage_references_from_images: bool = False,
+        require_healthcheck: bool = False,
+        instruction_counts_per_stage: bool = False,
+        require_tag_with_digest: bool = False,
+        max_instructions: int = 60,
+        run_lints: bool = True,
+        floating_tags: Optional[List[str]] = None,
+        validate_oci_labels: bool = False,
+        require_explicit_registry: bool = False,
+        allowed_registries: List[str] = [],
+        honor_check_directive: bool = True,
+        preserve_stage_case: bool = False,
+        max_run_length: Optional[int] = None,
+        max_run_commands: Optional[int] = None,
+    ) -> None: ...
+    def to_dict(self) -> Dict[str, Any]: ...
+    def __repr__(self) -> str: ...
 
 class Image:
     full: str
     components: Optional[ImageComponents]
 
+    def is_digest_pinned(self) -> bool:
+        """
+        Whether the reference includes a digest, with or without a tag.
+        """
+        ...
     def to_dict(self) -> Dict[str, Any]: ...
     def __repr__(self) -> str: ...
+    def __eq__(self, other: object) -> bool: ...
+    def __hash__(self) -> int: ...
+
+class ExposedPort:
+    port: int
+    protocol: str
+
+    def to_dict(self) -> Dict[str, Any]: ...
+    def __repr__(self) -> str: ...
+    def __eq__(self, other: object) -> bool: ...
+    def __hash__(self) -> int: ...
+
+class FileMetrics:
+    total_lines: int
+    comment_lines: int
+    blank_lines: int
+    instruction_lines: int
+
+    def to_dict(self) -> Dict[str, Any]: ...
+    def __repr__(self) -> str: ...
+    def __eq__(self, other: object) -> bool: ...
+    def __hash__(self) -> int: ...
 
 class ImageComponents:
     registry: Optional[str]
@@ -56,22 +431,66 @@ class ImageComponents:
     tag: Optional[str]
     digest: Optional[str]
 
+    def is_digest_pinned(self) -> bool:
+        """
+        Whether the reference includes a digest, with or without a tag.
+        """
+        ...
+    def digest_algorithm(self) -> Optional[str]:
+        """
+        Returns the algorithm part of the digest, e.g. 'sha256'.
+        """
+        ...
+    def to_dict(self) -> Dict[str, Any]: ...
+    def __repr__(self) -> str: ...
+    def __eq__(self, other: object) -> bool: ...
+    def __hash__(self) -> int: ...
+
+class InstructionEntry:
+    keyword: str
+    arguments: str
+    stage_index: int
+    line: int
+
     def to_dict(self) -> Dict[str, Any]: ...
     def __repr__(self) -> str: ...
 
 class InstructionStats:
     total_count: int
     by_type: Dict[str, int]
+    by_type_per_stage: Optional[List[Dict[str, int]]]
 
     def to_dict(self) -> Dict[str, Any]: ...
     def __repr__(self) -> str: ...
+    def __eq__(self, other: object) -> bool: ...
+    def __hash__(self) -> int: ...
 
 class MultistageAnalysis:
     is_multistage: bool
+    has_multiple_stages: bool
     stages_used_as_base_images: List[str]
     stages_copied_from: List[str]
     stages_added_from: List[str]
     unused_stages: List[str]
+    artifact_only_stages: List[str]
+
+    def to_dict(self) -> Dict[str, Any]: ...
+    def __repr__(self) -> str: ...
+    def __eq__(self, other: object) -> bool: ...
+    def __hash__(self) -> int: ...
+
+class StageAnalysis:
+    index: int
+    name: Optional[str]
+    base_image: str
+    args: Dict[str, Optional[str]]
+    labels: Dict[str, str]
+    env_vars: Dict[str, str]
+    depends_on: List[int]
+    platform: Optional[str]
+    user: Optional[str]
+    exposed_ports: List[str]
+    entrypoint: Optional[str]
 
     def to_dict(self) -> Dict[str, Any]: ...
     def __repr__(self) -> str: ...
@@ -2,16 +2,23 @@
 // Dockerfiles should now use named stages rather than numeric stages.
 
 use crate::constants;
+use crate::error::AnalyzeError;
+use crate::lints;
 use crate::models;
 use crate::models::KeyValueInstr;
 use crate::parse_utils;
 use docker_image::DockerImage;
-use parse_dockerfile::{AddInstruction, CopyInstruction, Instruction, Stage, parse};
-use std::collections::BTreeSet;
+use parse_dockerfile::{
+    AddInstruction, Command, CopyInstruction, Dockerfile, FromInstruction, HealthcheckArguments,
+    HealthcheckInstruction, Instruction, JsonOrStringArray, RunInstruction, Source, Stage,
+    UnescapedString, parse, parse_iter,
+};
+use rayon::prelude::*;
 use std::collections::HashMap;
-use std::error::Error;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
 
-trait HasOptions {
+pub(crate) trait HasOptions {
     fn options(&self) -> &[parse_dockerfile::Flag<'_>];
 }
 
@@ -27,19 +34,53 @@ impl HasOptions for AddInstruction<'_> {
     }
 }
 
-fn get_from_flag_val<T: HasOptions>(instruction: &T) -> Option<String> {
+impl HasOptions for RunInstruction<'_> {
+    fn options(&self) -> &[parse_dockerfile::Flag<'_>] {
+        &self.options
+    }
+}
+
+impl HasOptions for FromInstruction<'_> {
+    fn options(&self) -> &[parse_dockerfile::Flag<'_>] {
+        &self.options
+    }
+}
+
+impl HasOptions for HealthcheckInstruction<'_> {
+    fn options(&self) -> &[parse_dockerfile::Flag<'_>] {
+        &self.options
+    }
+}
+
+fn get_flag_val<T: HasOptions + ?Sized>(instruction: &T, name: &str) -> Option<String> {
     for flag in instruction.options() {
         let flag_name = &flag.name.value;
         let flag_val = flag.value.as_ref().map(|v| &v.value);
-        if flag_name.as_ref() == constants::FROM
-            && let Some(from_value) = flag_val
+        if flag_name.as_ref() == name
+            && let Some(value) = flag_val
         {
-            return Some(from_value.to_string());
+            return Some(value.to_string());
         }
     }
     None
 }
 
+// Whether a boolean flag such as `--link` is set, either bare or with a value
+// other than `false`.
+fn has_flag<T: HasOptions + ?Sized>(instruction: &T, name: &str) -> bool {
+    instruction.options().iter().any(|flag| {
+        flag.name.value.as_ref() == name
+            && flag
+                .value
+                .as_ref()
+                .is_none_or(|v| !v.value.eq_ignore_ascii_case("false"))
+    })
+}
+
+pub(crate) fn get_from_flag_val<T: HasOptions + ?Sized>(instruction: &T) -> Option<String> {
+    get_flag_val(instruction, constants::FROM)
+}
+
 fn analyze_multistage(
     num_stages: usize,
     images: &BTreeSet<String>,
@@ -66,14 +107,22 @@ fn analyze_multistage(
         .collect();
 
     let unused_stages = stage_names.difference(&used_stages);
-    let is_multistage = num_stages >= 2 && !used_stages.is_empty();
+    let artifact_only_stages = stages_copied_from
+        .union(&stages_added_from)
+        .filter(|name| !stages_used_as_base_images.contains(*name))
+        .cloned()
+        .collect();
+    let has_multiple_stages = num_stages >= 2;
+    let is_multistage = has_multiple_stages && !used_stages.is_empty();
 
     models::MultistageAnalysis {
         is_multistage,
+        has_multiple_stages,
         stages_used_as_base_images: stages_used_as_base_images.into_iter().collect(),
         stages_copied_from: stages_copied_from.into_iter().collect(),
         stages_added_from: stages_added_from.into_iter().collect(),
         unused_stages: unused_stages.into_iter().cloned().collect(),
+        artifact_only_stages,
     }
 }
 
@@ -102,13 +151,109 @@ fn get_parsed_images(images: &BTreeSet<String>) -> Vec<models::Image> {
     parsed_images
 }
 
-pub fn analyze_dockerfile(body: &str) -> Result<models::Analysis, Box<dyn Error>> {
-    let df = parse(body)?;
+fn parse_body(body: &str) -> Result<Dockerfile<'_>, AnalyzeError> {
+    check_has_instructions(body)?;
+    parse(body).map_err(|e| parse_error(body, &e))
+}
+
+// The parser reports input without instructions as a missing FROM, which is
+// misleading for empty and comment-only bodies, so those are caught first.
+fn check_has_instructions(body: &str) -> Result<(), AnalyzeError> {
+    let mut lines = body
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .peekable();
+    if lines.peek().is_none() {
+        return Err(AnalyzeError::EmptyInput);
+    }
+    if lines.all(|line| line.starts_with('#')) {
+        return Err(AnalyzeError::CommentsOnly);
+    }
+    Ok(())
+}
+
+fn parse_error(body: &str, e: &parse_dockerfile::Error) -> AnalyzeError {
+    let message = e.to_string();
+    if let Some(message) = instruction_before_from(body, &message) {
+        AnalyzeError::InstructionBeforeFrom(message)
+    } else if message.starts_with("duplicate stage name") {
+        AnalyzeError::DuplicateStage(message)
+    } else {
+        AnalyzeError::ParseError(healthcheck_without_command(&message).unwrap_or(message))
+    }
+}
+
+// The parser rejects anything but ARG before the first FROM with a bare
+// "expected FROM", so name the offending instruction instead.
+fn instruction_before_from(body: &str, message: &str) -> Option<String> {
+    let line: usize = message
+        .strip_prefix("expected FROM at line ")?
+        .split(' ')
+        .next()?
+        .parse()
+        .ok()?;
+    let keyword = body
+        .lines()
+        .nth(line.checked_sub(1)?)?
+        .split_whitespace()
+        .next()?;
+    Some(format!(
+        "{} at line {line} comes before the first FROM; only ARG instructions, parser directives and comments may precede FROM",
+        keyword.to_uppercase()
+    ))
+}
+
+// A HEALTHCHECK with options but no test command also fails to parse, with
+// "expected CMD or NONE" pointing past the options.
+fn healthcheck_without_command(message: &str) -> Option<String> {
+    let line = message
+        .strip_prefix("expected CMD or NONE at line ")?
+        .split(' ')
+        .next()?;
+    Some(format!(
+        "HEALTHCHECK at line {line} has no test command; add CMD <command> or use HEALTHCHECK NONE"
+    ))
+}
+
+pub fn analyze_dockerfile(body: &str) -> Result<models::Analysis, AnalyzeError> {
+    analyze_dockerfile_with_options(body, &models::AnalysisOptions::default())
+}
+
+pub fn analyze_dockerfile_with_options(
+    body: &str,
+    options: &models::AnalysisOptions,
+) -> Result<models::Analysis, AnalyzeError> {
+    let body = &parse_utils::normalize_line_endings(body);
+    let df = parse_body(body)?;
     let stages: Vec<_> = df.stages().collect();
     let num_stages = stages.len();
+    let escape_char = df
+        .parser_directives
+        .escape
+        .as_ref()
+        .map_or(constants::DEFAULT_ESCAPE_CHAR, |d| d.value.value);
+    let syntax_frontend = df
+        .parser_directives
+        .syntax
+        .as_ref()
+        .map(|d| d.value.value.to_string());
+    let skipped_checks = df
+        .parser_directives
+        .check
+        .as_ref()
+        .map(|d| parse_utils::check_directive_skips(d.value.value))
+        .unwrap_or_default();
 
     let (images, stage_names) = extract_stage_info(&stages);
-    let (copy_from_stages, add_from_stages) = extract_from_references(&df.instructions);
+    let stage_breakdown = extract_stage_breakdown(body, &stages);
+    let final_base_chain = extract_final_base_chain(&stage_breakdown);
+    let removable_stages = extract_removable_stages(&stage_breakdown);
+    let is_windows = extract_is_windows(&final_base_chain, &df.instructions);
+    let (distinct_base_images, reused_base_images) =
+        extract_base_image_reuse(&stage_breakdown, &stage_names);
+    let (copy_from_stages, add_from_stages) =
+        extract_from_references(&df.instructions, &stage_names);
 
     let multistage_analysis = analyze_multistage(
         num_stages,
@@ -118,26 +263,277 @@ pub fn analyze_dockerfile(body: &str) -> Result<models::Analysis, Box<dyn Error>
         &add_from_stages,
     );
 
-    let parsed_images: Vec<models::Image> = get_parsed_images(&images);
+    let parsed_images: Vec<models::Image> = match options.exclude_stage_references_from_images {
+        true => get_parsed_images(&images.difference(&stage_names).cloned().collect()),
+        false => get_parsed_images(&images),
+    };
+    let digest_pinned_images = parsed_images
+        .iter()
+        .filter(|image| image.is_digest_pinned())
+        .map(|image| image.full.clone())
+        .collect();
+    let copy_from_images = get_parsed_images(
+        &copy_from_stages
+            .iter()
+            .filter(|r| is_external_reference(r, &stage_names))
+            .cloned()
+            .collect(),
+    );
+    let registries = extract_registries(
+        images
+            .iter()
+            .chain(&copy_from_stages)
+            .chain(&add_from_stages),
+        &stage_names,
+    );
     let exposed_ports = extract_ports(&df.instructions);
-    let instructions = extract_instructions(&df.instructions);
+    let parsed_ports = extract_parsed_ports(&df.instructions);
+    let instructions = extract_instructions(
+        &df.instructions,
+        &stages,
+        options.instruction_counts_per_stage,
+    );
+    let flags_used = extract_flags_used(&df.instructions);
+    let file_metrics = extract_file_metrics(body);
+    let path_modifications = extract_path_modifications(&df.instructions);
+    let per_stage_distinct_instruction_kinds = extract_distinct_instruction_kinds(&stages);
+    let ordered_instructions = extract_ordered_instructions(body, &df.instructions);
+    let copy_operations = extract_copy_operations(body, &df.instructions);
+    // RUN commands are only tokenized for the lints and the run-related
+    // fields, including created_users, so structural-only callers skip that
+    // work along with the lints.
+    let run_operations = match options.run_lints {
+        true => extract_run_operations(body, &df.instructions),
+        false => vec![],
+    };
+    let has_offline_runs = run_operations
+        .iter()
+        .any(|op| op.network.as_deref() == Some(constants::NETWORK_NONE));
+    let uses_copy_link = copy_operations.iter().any(|op| op.link);
+    let created_users = match options.run_lints {
+        true => extract_created_users(&ordered_instructions, escape_char),
+        false => vec![],
+    };
+    let copy_optimization_suggestions = match options.run_lints {
+        true => lints::copy_optimization_suggestions(&ordered_instructions),
+        false => vec![],
+    };
     let kv_pairs = extract_key_value_pairs(&df.instructions);
+    let env_count = kv_pairs.env_vars.len();
+    let arg_count = kv_pairs.args.len();
+    let label_count = kv_pairs.labels.len();
+    let global_args = extract_global_args(&df);
+    let effective_env = extract_effective_env(&stages);
+    let stop_signal = extract_stop_signal(&stages);
+    let maintainer = extract_maintainer(&df.instructions);
+    let arg_usages = extract_arg_usages(&kv_pairs.args, &ordered_instructions);
+    let required_build_args = extract_required_build_args(&df.instructions, &arg_usages);
+    let findings = match options.run_lints {
+        true => lints::run_lints(&lints::LintContext {
+            body,
+            instructions: &df.instructions,
+            ordered_instructions: &ordered_instructions,
+            copy_operations: &copy_operations,
+            run_operations: &run_operations,
+            stages: &stage_breakdown,
+            created_users: &created_users,
+            skipped_checks: &skipped_checks,
+            escape_char,
+            options,
+        }),
+        false => vec![],
+    };
+
+    let (quality_score, quality_deductions) = extract_quality_score(&findings);
 
     Ok(models::Analysis {
         num_stages,
+        escape_char,
+        syntax_frontend,
+        skipped_checks,
+        is_windows,
+        final_base_chain,
         images: parsed_images,
-        stage_names: stage_names.into_iter().collect(),
+        distinct_base_images,
+        reused_base_images,
+        digest_pinned_images,
+        registries,
+        stage_names: match options.preserve_stage_case {
+            true => original_stage_names(&stages, &stage_names),
+            false => stage_names.into_iter().collect(),
+        },
+        removable_stages,
+        stages: stage_breakdown,
         copy_from_stages: copy_from_stages.into_iter().collect(),
+        copy_from_images,
         add_from_stages: add_from_stages.into_iter().collect(),
         multistage_analysis,
         exposed_ports: exposed_ports.into_iter().collect(),
+        parsed_ports,
         instructions,
+        flags_used,
+        file_metrics,
+        per_stage_distinct_instruction_kinds,
+        ordered_instructions,
+        copy_operations,
+        copy_optimization_suggestions,
+        run_operations,
+        has_offline_runs,
+        uses_copy_link,
+        created_users,
         args: kv_pairs.args,
+        global_args,
+        arg_usages,
+        required_build_args,
         labels: kv_pairs.labels,
         env_vars: kv_pairs.env_vars,
+        env_count,
+        arg_count,
+        label_count,
+        effective_env,
+        path_modifications,
+        stop_signal,
+        maintainer,
+        findings,
+        quality_score,
+        quality_deductions,
     })
 }
 
+/// Analyzes only what `docker build --target <target>` builds: the target
+/// stage and the stages it transitively builds on or copies from.
+///
+/// Lines of the other stages are blanked out rather than removed, so line
+/// numbers still refer to the original body while stage indices refer to the
+/// remaining stages. The global ARGs before the first FROM are kept.
+/// `file_metrics` describes the whole original body, not the kept stages.
+pub fn analyze_dockerfile_target(
+    body: &str,
+    target: &str,
+    options: &models::AnalysisOptions,
+) -> Result<models::Analysis, AnalyzeError> {
+    let body = &parse_utils::normalize_line_endings(body);
+    let df = parse_body(body)?;
+    let stages: Vec<_> = df.stages().collect();
+    let stage_breakdown = extract_stage_breakdown(body, &stages);
+    let target_lc = target.to_lowercase();
+    let target_index = stage_breakdown
+        .iter()
+        .position(|s| s.name.as_deref() == Some(target_lc.as_str()))
+        .ok_or_else(|| AnalyzeError::TargetNotFound(target.to_string()))?;
+
+    let keep = reachable_stages(&stage_breakdown, target_index);
+
+    let from_lines: Vec<usize> = stages
+        .iter()
+        .map(|stage| parse_utils::line_number(body, stage.from.from.span.start))
+        .collect();
+    let subset: Vec<&str> = body
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let stage = from_lines.iter().rposition(|&from| from <= i + 1);
+            match stage {
+                Some(stage) if !keep.contains(&stage) => "",
+                _ => line,
+            }
+        })
+        .collect();
+
+    let mut analysis = analyze_dockerfile_with_options(&subset.join("\n"), options)?;
+    analysis.file_metrics = extract_file_metrics(body);
+    Ok(analysis)
+}
+
+/// Analyzes a Dockerfile given as raw bytes. Valid UTF-8 is analyzed as is,
+/// with any byte order mark stripped. Otherwise invalid sequences are
+/// replaced with U+FFFD and, when lints run, an invalid-utf8 warning points
+/// at the first of them.
+pub fn analyze_dockerfile_bytes(
+    data: &[u8],
+    options: &models::AnalysisOptions,
+) -> Result<models::Analysis, AnalyzeError> {
+    let error = match std::str::from_utf8(data) {
+        Ok(body) => return analyze_dockerfile_with_options(body, options),
+        Err(error) => error,
+    };
+    let body = String::from_utf8_lossy(data);
+    let mut analysis = analyze_dockerfile_with_options(&body, options)?;
+    if options.run_lints {
+        let offset = error.valid_up_to();
+        let line = data[..offset].iter().filter(|&&b| b == b'\n').count() + 1;
+        analysis
+            .findings
+            .insert(0, lints::invalid_utf8(offset, line));
+        (analysis.quality_score, analysis.quality_deductions) =
+            extract_quality_score(&analysis.findings);
+    }
+
+    Ok(analysis)
+}
+
+/// Analyzes several Dockerfiles, returning one result per input in input
+/// order. With `parallel` set the inputs are spread across rayon's pool.
+pub fn analyze_dockerfiles(
+    bodies: &[String],
+    options: &models::AnalysisOptions,
+    parallel: bool,
+) -> Vec<Result<models::Analysis, AnalyzeError>> {
+    let analyze = |body: &String| analyze_dockerfile_with_options(body, options);
+    match parallel {
+        true => bodies.par_iter().map(analyze).collect(),
+        false => bodies.iter().map(analyze).collect(),
+    }
+}
+
+/// Runs only the multistage part of the analysis, skipping instruction,
+/// env, label, port and lint extraction.
+pub fn analyze_multistage_only(body: &str) -> Result<models::MultistageAnalysis, AnalyzeError> {
+    let body = &parse_utils::normalize_line_endings(body);
+    let df = parse_body(body)?;
+    let stages: Vec<_> = df.stages().collect();
+    let (images, stage_names) = extract_stage_info(&stages);
+    let (copy_from_stages, add_from_stages) =
+        extract_from_references(&df.instructions, &stage_names);
+
+    Ok(analyze_multistage(
+        stages.len(),
+        &images,
+        &stage_names,
+        &copy_from_stages,
+        &add_from_stages,
+    ))
+}
+
+/// Yields the keyword and one-based line of every instruction in the
+/// Dockerfile without building an Analysis.
+///
+/// Instructions are parsed one at a time as the iterator advances, so the
+/// instruction list is never built. A parse error is yielded in place of the
+/// instruction it stops at and ends the iteration; unlike the analyze
+/// functions, repeated stage names are not reported.
+///
+/// Line numbers are counted incrementally as the instructions are visited,
+/// so iterating is linear in the size of the body. The iterator borrows the
+/// body, so only a byte order mark is stripped; CRLF endings are left as is
+/// and still yield the same lines.
+pub fn instruction_keywords(
+    body: &str,
+) -> Result<impl Iterator<Item = Result<(&'static str, usize), AnalyzeError>> + '_, AnalyzeError> {
+    let body = parse_utils::strip_bom(body);
+    check_has_instructions(body)?;
+    let instructions = parse_iter(body).map_err(|e| parse_error(body, &e))?;
+    let mut offset = 0;
+    let mut line = 1;
+    Ok(instructions.map(move |ins| {
+        let ins = ins.map_err(|e| parse_error(body, &e))?;
+        let start = instruction_span(&ins).start;
+        line += body[offset..start].matches('\n').count();
+        offset = start;
+        Ok((instruction_keyword(&ins), line))
+    }))
+}
+
 fn extract_key_value_pairs(instructions: &[Instruction]) -> models::KeyValueInstr {
     let mut args: HashMap<String, Option<String>> = HashMap::new();
     let mut labels: HashMap<String, String> = HashMap::new();
@@ -165,568 +561,3974 @@ fn extract_key_value_pairs(instructions: &[Instruction]) -> models::KeyValueInst
     }
 }
 
-fn extract_instructions(instructions: &[Instruction]) -> models::InstructionStats {
-    let mut by_type = HashMap::new();
+fn instruction_keyword(ins: &Instruction) -> &'static str {
+    match ins {
+        Instruction::Add(..) => constants::ADD,
+        Instruction::Arg(..) => constants::ARG,
+        Instruction::Cmd(..) => constants::CMD,
+        Instruction::Copy(..) => constants::COPY,
+        Instruction::Entrypoint(..) => constants::ENTRYPOINT,
+        Instruction::Env(..) => constants::ENV,
+        Instruction::Expose(..) => constants::EXPOSE,
+        Instruction::From(..) => constants::FROM_UC,
+        Instruction::Healthcheck(..) => constants::HEALTHCHECK,
+        Instruction::Label(..) => constants::LABEL,
+        Instruction::Maintainer(..) => constants::MAINTAINER,
+        Instruction::Onbuild(..) => constants::ONBUILD,
+        Instruction::Run(..) => constants::RUN,
+        Instruction::Shell(..) => constants::SHELL,
+        Instruction::Stopsignal(..) => constants::STOPSIGNAL,
+        Instruction::User(..) => constants::USER,
+        Instruction::Volume(..) => constants::VOLUME,
+        Instruction::Workdir(..) => constants::WORKDIR,
+        &_ => "",
+    }
+}
+
+fn command_end(cmd: &Command) -> usize {
+    match cmd {
+        Command::Exec(exec) => exec.span.end,
+        Command::Shell(shell) => shell.span.end,
+        &_ => 0,
+    }
+}
+
+// Returns the byte span of an instruction, from the start of its keyword to
+// the end of its last argument.
+fn instruction_span(ins: &Instruction) -> Range<usize> {
+    let (keyword, end) = match ins {
+        Instruction::Add(a) => (&a.add, a.dest.span.end),
+        Instruction::Arg(a) => (&a.arg, a.arguments.span.end),
+        Instruction::Cmd(c) => (&c.cmd, command_end(&c.arguments)),
+        Instruction::Copy(c) => (&c.copy, c.dest.span.end),
+        Instruction::Entrypoint(e) => (&e.entrypoint, command_end(&e.arguments)),
+        Instruction::Env(e) => (&e.env, e.arguments.span.end),
+        Instruction::Expose(e) => (&e.expose, e.arguments.last().map_or(0, |a| a.span.end)),
+        Instruction::From(f) => (
+            &f.from,
+            f.as_
+                .as_ref()
+                .map_or(f.image.span.end, |(_, name)| name.span.end),
+        ),
+        Instruction::Healthcheck(h) => (
+            &h.healthcheck,
+            match &h.arguments {
+                HealthcheckArguments::Cmd { arguments, .. } => command_end(arguments),
+                HealthcheckArguments::None { none, .. } => none.span.end,
+                &_ => 0,
+            },
+        ),
+        Instruction::Label(l) => (&l.label, l.arguments.span.end),
+        Instruction::Maintainer(m) => (&m.maintainer, m.name.span.end),
+        Instruction::Onbuild(o) => (&o.onbuild, instruction_span(&o.instruction).end),
+        Instruction::Run(r) => (
+            &r.run,
+            r.here_docs
+                .iter()
+                .map(|h| h.span.end)
+                .fold(command_end(&r.arguments), usize::max),
+        ),
+        Instruction::Shell(s) => (&s.shell, s.arguments.last().map_or(0, |a| a.span.end)),
+        Instruction::Stopsignal(s) => (&s.stopsignal, s.arguments.span.end),
+        Instruction::User(u) => (&u.user, u.arguments.span.end),
+        Instruction::Volume(v) => (
+            &v.volume,
+            match &v.arguments {
+                JsonOrStringArray::Json(json) => json.span.end,
+                JsonOrStringArray::String(strings) => strings.last().map_or(0, |a| a.span.end),
+            },
+        ),
+        Instruction::Workdir(w) => (&w.workdir, w.arguments.span.end),
+        &_ => return 0..0,
+    };
+    keyword.span.start..end.max(keyword.span.end)
+}
+
+// Returns the text following an instruction's keyword. The span is extended to
+// the end of its final line so closing brackets of JSON arrays are included.
+fn instruction_arguments<'a>(body: &'a str, ins: &Instruction) -> &'a str {
+    let span = instruction_span(ins);
+    let keyword_end = span.start + instruction_keyword(ins).len();
+    let line_end = body[span.end..]
+        .find('\n')
+        .map_or(body.len(), |i| span.end + i);
+    body[keyword_end..line_end].trim()
+}
+
+fn extract_ordered_instructions(
+    body: &str,
+    instructions: &[Instruction],
+) -> Vec<models::InstructionEntry> {
+    let mut stage_index: Option<usize> = None;
+    let mut entries = vec![];
     for ins in instructions {
-        let s = match ins {
-            Instruction::Add(..) => constants::ADD.to_string(),
-            Instruction::Arg(..) => constants::ARG.to_string(),
-            Instruction::Cmd(..) => constants::CMD.to_string(),
-            Instruction::Copy(..) => constants::COPY.to_string(),
-            Instruction::Entrypoint(..) => constants::ENTRYPOINT.to_string(),
-            Instruction::Env(..) => constants::ENV.to_string(),
-            Instruction::Expose(..) => constants::EXPOSE.to_string(),
-            Instruction::From(..) => constants::FROM_UC.to_string(),
-            Instruction::Healthcheck(..) => constants::HEALTHCHECK.to_string(),
-            Instruction::Label(..) => constants::LABEL.to_string(),
-            Instruction::Maintainer(..) => constants::MAINTAINER.to_string(),
-            Instruction::Onbuild(..) => constants::ONBUILD.to_string(),
-            Instruction::Run(..) => constants::RUN.to_string(),
-            Instruction::Shell(..) => constants::SHELL.to_string(),
-            Instruction::Stopsignal(..) => constants::STOPSIGNAL.to_string(),
-            Instruction::User(..) => constants::USER.to_string(),
-            Instruction::Volume(..) => constants::VOLUME.to_string(),
-            Instruction::Workdir(..) => constants::WORKDIR.to_string(),
-            &_ => "".to_string(),
+        if let Instruction::From(..) = ins {
+            stage_index = Some(stage_index.map_or(0, |i| i + 1));
+        }
+        entries.push(models::InstructionEntry {
+            keyword: instruction_keyword(ins).to_string(),
+            arguments: instruction_arguments(body, ins).to_string(),
+            stage_index: stage_index.unwrap_or(0),
+            line: parse_utils::line_number(body, instruction_span(ins).start),
+        });
+    }
+
+    entries
+}
+
+// Like ENV, STOPSIGNAL carries from a stage into any stage built `FROM` it.
+fn extract_stop_signal(stages: &[Stage]) -> Option<String> {
+    let mut stage_signals: Vec<Option<String>> = vec![];
+    let mut stage_indexes: HashMap<String, usize> = HashMap::new();
+
+    for (i, stage) in stages.iter().enumerate() {
+        let base = stage.from.image.value.to_lowercase();
+        let mut signal = stage_indexes
+            .get(&base)
+            .and_then(|&base_index| stage_signals[base_index].clone());
+        for ins in stage.instructions {
+            if let Instruction::Stopsignal(s) = ins {
+                signal = Some(s.arguments.value.trim().to_string());
+            }
+        }
+        if let Some((_, name)) = &stage.from.as_ {
+            stage_indexes.insert(name.value.to_lowercase(), i);
+        }
+        stage_signals.push(signal);
+    }
+
+    stage_signals.pop().flatten()
+}
+
+// MAINTAINER is deprecated but still sets the image author; the last one
+// wins, as with repeated LABELs.
+fn extract_maintainer(instructions: &[Instruction]) -> Option<String> {
+    instructions.iter().rev().find_map(|ins| match ins {
+        Instruction::Maintainer(m) => Some(m.name.value.trim().to_string()),
+        _ => None,
+    })
+}
+
+// ENV carries from a stage into any stage built `FROM` it, but not across
+// unrelated stages, so each stage starts from its base stage's environment.
+fn extract_effective_env(stages: &[Stage]) -> HashMap<String, String> {
+    let mut stage_envs: Vec<HashMap<String, String>> = vec![];
+    let mut stage_indexes: HashMap<String, usize> = HashMap::new();
+
+    for (i, stage) in stages.iter().enumerate() {
+        let base = stage.from.image.value.to_lowercase();
+        let mut env = match stage_indexes.get(&base) {
+            Some(&base_index) => stage_envs[base_index].clone(),
+            None => HashMap::new(),
         };
-        *by_type.entry(s).or_insert(0) += 1;
+        for ins in stage.instructions {
+            if let Instruction::Env(e) = ins {
+                env.extend(parse_utils::parse_kv_instruction(
+                    e.arguments.value.as_ref(),
+                ));
+            }
+        }
+        if let Some((_, name)) = &stage.from.as_ {
+            stage_indexes.insert(name.value.to_lowercase(), i);
+        }
+        stage_envs.push(env);
     }
 
-    models::InstructionStats {
-        total_count: instructions.len() as u32,
-        by_type,
+    stage_envs.pop().unwrap_or_default()
+}
+
+fn extract_global_args(df: &Dockerfile) -> HashMap<String, Option<String>> {
+    let mut global_args = HashMap::new();
+    for arg in df.global_args() {
+        global_args.extend(parse_utils::parse_kv_instruction_opt_val(
+            arg.arguments.value.as_ref(),
+        ));
     }
+
+    global_args
 }
 
-fn extract_ports(instructions: &[Instruction]) -> BTreeSet<String> {
-    let mut all_ports = BTreeSet::new();
-    for ins in instructions {
-        let mut ports = match ins {
-            Instruction::Expose(exp) => exp.arguments.iter().map(|x| x.value.to_string()).collect(),
-            _ => BTreeSet::new(),
+// Resolves the stages each stage depends on, either as its base image or
+// through COPY/ADD --from. References are matched by stage name only.
+fn stage_dependencies(stages: &[Stage], index: usize) -> Vec<usize> {
+    let mut stage_indices: HashMap<String, usize> = HashMap::new();
+    for (i, stage) in stages.iter().enumerate() {
+        if let Some((_, name)) = &stage.from.as_ {
+            stage_indices.entry(name.value.to_lowercase()).or_insert(i);
+        }
+    }
+
+    let stage = &stages[index];
+    let mut references = vec![stage.from.image.value.to_lowercase()];
+    for ins in stage.instructions {
+        let from_val = match ins {
+            Instruction::Copy(c) => get_from_flag_val(c),
+            Instruction::Add(a) => get_from_flag_val(a),
+            _ => None,
         };
-        all_ports.append(&mut ports);
+        references.extend(from_val.map(|v| v.to_lowercase()));
     }
 
-    all_ports
+    let mut depends_on: BTreeSet<usize> = BTreeSet::new();
+    for (i, reference) in references.iter().enumerate() {
+        // "FROM node AS node" names the image, not the stage itself.
+        match stage_indices.get(reference) {
+            Some(&dep) if !(i == 0 && dep == index) => {
+                depends_on.insert(dep);
+            }
+            _ => {}
+        }
+    }
+
+    depends_on.into_iter().collect()
 }
 
-fn extract_stage_info(stages: &[Stage]) -> (BTreeSet<String>, BTreeSet<String>) {
-    let images = stages
+fn extract_stage_breakdown(body: &str, stages: &[Stage]) -> Vec<models::StageAnalysis> {
+    let stage_names = extract_stage_names(stages);
+    stages
         .iter()
-        .map(|s| {
-            let value = s.from.image.value.to_string();
-            match value.starts_with('$') {
-                true => value,
-                false => value.to_lowercase(),
+        .enumerate()
+        .map(|(index, stage)| {
+            let mut args = HashMap::new();
+            let mut labels = HashMap::new();
+            let mut env_vars = HashMap::new();
+            let mut user = None;
+            let mut exposed_ports = vec![];
+            let mut entrypoint = None;
+            for ins in stage.instructions {
+                match ins {
+                    Instruction::Arg(a) => args.extend(parse_utils::parse_kv_instruction_opt_val(
+                        a.arguments.value.as_ref(),
+                    )),
+                    Instruction::Label(l) => labels.extend(parse_utils::parse_kv_instruction(
+                        l.arguments.value.as_ref(),
+                    )),
+                    Instruction::Env(e) => env_vars.extend(parse_utils::parse_kv_instruction(
+                        e.arguments.value.as_ref(),
+                    )),
+                    Instruction::User(u) => user = Some(u.arguments.value.trim().to_string()),
+                    Instruction::Expose(e) => {
+                        exposed_ports.extend(e.arguments.iter().map(|p| p.value.to_string()))
+                    }
+                    Instruction::Entrypoint(..) => {
+                        entrypoint = Some(instruction_arguments(body, ins).to_string())
+                    }
+                    _ => {}
+                }
+            }
+            models::StageAnalysis {
+                index,
+                name: stage
+                    .from
+                    .as_
+                    .as_ref()
+                    .map(|(_, name)| name.value.to_lowercase()),
+                base_image: normalize_image(stage, &stage_names),
+                args,
+                labels,
+                env_vars,
+                depends_on: stage_dependencies(stages, index),
+                platform: get_flag_val(stage.from, constants::PLATFORM),
+                user,
+                exposed_ports,
+                entrypoint,
             }
         })
-        .collect();
+        .collect()
+}
 
-    let stage_names = stages
+// Counts the distinct instruction keywords in each stage, FROM included.
+// Global ARGs before the first FROM belong to no stage.
+fn extract_distinct_instruction_kinds(stages: &[Stage]) -> Vec<usize> {
+    stages
         .iter()
-        .filter_map(|s| s.from.as_.as_ref())
-        .map(|stage_name| stage_name.1.value.to_string().to_lowercase())
-        .collect();
+        .map(|stage| {
+            let mut kinds: BTreeSet<&str> =
+                stage.instructions.iter().map(instruction_keyword).collect();
+            kinds.insert(constants::FROM_UC);
+            kinds.len()
+        })
+        .collect()
+}
 
-    (images, stage_names)
+// Counts the external base images and lists those used by more than one
+// stage. Stages built on an earlier stage are not counted.
+fn extract_base_image_reuse(
+    stages: &[models::StageAnalysis],
+    stage_names: &BTreeSet<String>,
+) -> (usize, Vec<String>) {
+    let mut uses: BTreeMap<&str, usize> = BTreeMap::new();
+    for stage in stages {
+        if !stage_names.contains(&stage.base_image) {
+            *uses.entry(stage.base_image.as_str()).or_default() += 1;
+        }
+    }
+
+    let reused = uses
+        .iter()
+        .filter(|(_, count)| **count > 1)
+        .map(|(image, _)| image.to_string())
+        .collect();
+    (uses.len(), reused)
 }
 
-fn extract_from_references(instructions: &[Instruction]) -> (BTreeSet<String>, BTreeSet<String>) {
-    let mut copy_from_stages = BTreeSet::new();
-    let mut add_from_stages = BTreeSet::new();
+fn extract_arg_usages(
+    args: &HashMap<String, Option<String>>,
+    ordered_instructions: &[models::InstructionEntry],
+) -> HashMap<String, Vec<String>> {
+    args.keys()
+        .map(|name| {
+            let keywords = ordered_instructions
+                .iter()
+                .filter(|ins| parse_utils::references_var(&ins.arguments, name))
+                .map(|ins| ins.keyword.clone())
+                .collect();
+            (name.clone(), keywords)
+        })
+        .collect()
+}
 
+// An ARG is required when it is used and one of its declarations has no
+// value in scope. A stage ARG without a default inherits the default of the
+// global ARG (one before the first FROM) or of an earlier declaration in the
+// same stage.
+fn extract_required_build_args(
+    instructions: &[Instruction],
+    arg_usages: &HashMap<String, Vec<String>>,
+) -> Vec<String> {
+    let mut global_defaults: BTreeSet<String> = BTreeSet::new();
+    let mut stage_defaults: Option<BTreeSet<String>> = None;
+    let mut unresolved: BTreeSet<String> = BTreeSet::new();
     for ins in instructions {
-        let from_val = match ins {
-            Instruction::Copy(c) => get_from_flag_val(c),
-            Instruction::Add(a) => get_from_flag_val(a),
+        let a = match ins {
+            Instruction::From(..) => {
+                stage_defaults = Some(BTreeSet::new());
+                continue;
+            }
+            Instruction::Arg(a) => a,
             _ => continue,
         };
-
-        if let Some(val) = from_val {
-            let target_set = match ins {
-                Instruction::Copy(_) => &mut copy_from_stages,
-                Instruction::Add(_) => &mut add_from_stages,
-                _ => unreachable!(),
-            };
-            target_set.insert(val.to_lowercase());
+        for (name, value) in parse_utils::parse_kv_instruction_opt_val(a.arguments.value.as_ref()) {
+            let defaults = stage_defaults.as_mut().unwrap_or(&mut global_defaults);
+            if value.is_some() || defaults.contains(&name) {
+                defaults.insert(name);
+            } else if stage_defaults.is_none() || !global_defaults.contains(&name) {
+                unresolved.insert(name);
+            }
         }
     }
+    unresolved
+        .into_iter()
+        .filter(|name| arg_usages.get(name).is_some_and(|uses| !uses.is_empty()))
+        .collect()
+}
 
-    (copy_from_stages, add_from_stages)
+fn source_value(src: &Source) -> String {
+    match src {
+        Source::Path(path) => path.value.to_string(),
+        _ => constants::HEREDOC_SOURCE.to_string(),
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::vec;
+fn extract_copy_operations(body: &str, instructions: &[Instruction]) -> Vec<models::CopyOperation> {
+    let mut stage_index: Option<usize> = None;
+    let mut operations = vec![];
+    for ins in instructions {
+        let (keyword, src, dest, flags): (&str, &[Source], &UnescapedString, &dyn HasOptions) =
+            match ins {
+                Instruction::From(..) => {
+                    stage_index = Some(stage_index.map_or(0, |i| i + 1));
+                    continue;
+                }
+                Instruction::Copy(c) => (constants::COPY, &c.src, &c.dest, c),
+                Instruction::Add(a) => (constants::ADD, &a.src, &a.dest, a),
+                _ => continue,
+            };
+        let chmod = get_flag_val(flags, constants::CHMOD);
+        operations.push(models::CopyOperation {
+            instruction: keyword.to_string(),
+            sources: src.iter().map(source_value).collect(),
+            destination: dest.value.to_string(),
+            stage_index: stage_index.unwrap_or(0),
+            line: parse_utils::line_number(body, instruction_span(ins).start),
+            chmod_octal: chmod.as_deref().and_then(parse_utils::parse_chmod),
+            chmod,
+            from_source: get_from_flag_val(flags),
+            checksum: get_flag_val(flags, constants::CHECKSUM),
+            link: has_flag(flags, constants::LINK),
+        });
+    }
 
-    #[test]
-    fn test_multistage() {
-        let dockerfile = r#"
-FROM docker.abc.com/base-images/python:3.13-debian@sha256:55f1d15ef4c37870e23c03e89ad238940b55c8ede9f13fac4b7d71c7955f1053 AS base
+    operations
+}
 
-LABEL org.opencontainers.image.title="My App" \
-      org.opencontainers.image.version="1.0" \
-      org.opencontainers.image.authors="john@example.com"
+fn extract_run_operations(body: &str, instructions: &[Instruction]) -> Vec<models::RunOperation> {
+    let mut stage_index: Option<usize> = None;
+    let mut operations = vec![];
+    // SHELL applies until the end of its stage; inheriting it from a base
+    // stage is not tracked.
+    let mut shell: Option<Vec<String>> = None;
+    for ins in instructions {
+        let run = match ins {
+            Instruction::From(..) => {
+                stage_index = Some(stage_index.map_or(0, |i| i + 1));
+                shell = None;
+                continue;
+            }
+            Instruction::Shell(s) => {
+                shell = Some(s.arguments.iter().map(|a| a.value.to_string()).collect());
+                continue;
+            }
+            Instruction::Run(run) => run,
+            _ => continue,
+        };
+        let command = match &run.arguments {
+            Command::Exec(exec) => exec
+                .value
+                .iter()
+                .map(|arg| arg.value.as_ref())
+                .collect::<Vec<_>>()
+                .join(" "),
+            Command::Shell(shell) => shell.value.trim().to_string(),
+            &_ => String::new(),
+        };
+        operations.push(models::RunOperation {
+            command,
+            network: get_flag_val(run, constants::NETWORK),
+            shell: shell.clone(),
+            stage_index: stage_index.unwrap_or(0),
+            line: parse_utils::line_number(body, run.run.span.start),
+        });
+    }
 
-ENV PYTHONPATH=/src \
-    PYTHONUNBUFFERED=1 \
-    REQUESTS_CA_BUNDLE=/etc/ssl/certs/ca-certificates.crt \
-    PATH="/home/appuser/.local/bin:\$PATH"
-WORKDIR /src
-USER root:root
+    operations
+}
 
-RUN apt-get update && \
-    apt-get install --no-install-recommends -y postgresql-client curl git && \
-    apt-get autoremove -y && \
-    apt-get clean && \
-    rm -rf /var/lib/apt/lists/*
+// Starts from a perfect score and deducts the severity weight of each finding.
+// Returns the score and the points deducted per rule.
+fn extract_quality_score(findings: &[models::Finding]) -> (u8, HashMap<String, u32>) {
+    let mut deductions: HashMap<String, u32> = HashMap::new();
+    for finding in findings {
+        let weight = constants::SEVERITY_WEIGHTS
+            .iter()
+            .find(|(severity, _)| *severity == finding.severity)
+            .map_or(0, |(_, weight)| *weight);
+        *deductions.entry(finding.rule_id.clone()).or_default() += weight;
+    }
+    let total: u32 = deductions.values().sum();
+    let score = u32::from(constants::MAX_QUALITY_SCORE).saturating_sub(total);
+    (score as u8, deductions)
+}
 
-RUN pip install --no-cache-dir --upgrade pip
-COPY --chown=1000:1000 requirements.txt ./
-RUN pip install --no-cache-dir -r requirements.txt
+fn is_windows_image(image: &str) -> bool {
+    let tag = image
+        .rsplit_once(':')
+        .filter(|(_, tag)| !tag.contains('/'))
+        .map_or("", |(_, tag)| tag);
+    constants::WINDOWS_IMAGE_PATHS
+        .iter()
+        .any(|path| image.starts_with(path))
+        || constants::WINDOWS_IMAGE_TAGS
+            .iter()
+            .any(|fragment| tag.contains(fragment))
+}
 
-FROM base AS test
-COPY --chown=1000:1000 test-requirements.txt ./
-USER 1000:1000
-RUN pip install --user --no-cache-dir -r test-requirements.txt
-COPY ./app ./app
-COPY ./test ./test
+// Follows the final stage's FROM through earlier stages until it reaches an
+// external image or `scratch`. Each entry is the image or stage named in a
+// FROM, so the last one is the image the final stage is ultimately built on.
+fn extract_final_base_chain(stage_breakdown: &[models::StageAnalysis]) -> Vec<String> {
+    let mut chain = vec![];
+    let Some(mut stage) = stage_breakdown.last() else {
+        return chain;
+    };
+    loop {
+        chain.push(stage.base_image.clone());
+        if stage.base_image == constants::SCRATCH {
+            break;
+        }
+        match stage_breakdown[..stage.index]
+            .iter()
+            .rev()
+            .find(|s| s.name.as_deref() == Some(stage.base_image.as_str()))
+        {
+            Some(base) => stage = base,
+            None => break,
+        }
+    }
 
-FROM base
-COPY --chown=1000:1000 ./app ./app
-USER 1000:1000
-ARG GIT_COMMIT
-ENV GIT_COMMIT=\$GIT_COMMIT
-EXPOSE 5000
+    chain
+}
 
-CMD ["uvicorn", "--host", "0.0.0.0", "--port", "5000", "app.main:app"]"#;
-        let msa = models::MultistageAnalysis {
-            is_multistage: true,
-            stages_used_as_base_images: vec!["base".to_string()],
-            stages_copied_from: vec![],
-            stages_added_from: vec![],
-            unused_stages: vec!["test".to_string()],
-        };
-        let images: Vec<models::Image> = vec![models::Image {
-            full: "base".to_string(),
-            components: Some(models::ImageComponents {
-                registry: None,
-                name: "base".to_string(),
-                tag: None,
-                digest: None,
-            }),
-        }, models::Image {
-        full: "docker.abc.com/base-images/python:3.13-debian@sha256:55f1d15ef4c37870e23c03e89ad238940b55c8ede9f13fac4b7d71c7955f1053".to_string(),
-        components: Some(models::ImageComponents {
-            registry: Some("docker.abc.com".to_string()),
-            name: "base-images/python".to_string(),
-            tag: Some("3.13-debian".to_string()),
-            digest: Some("sha256:55f1d15ef4c37870e23c03e89ad238940b55c8ede9f13fac4b7d71c7955f1053".to_string()),
+// The image targets Windows when the final stage is ultimately built on a
+// Windows base image, or when a stage sets a Windows SHELL.
+fn extract_is_windows(final_base_chain: &[String], instructions: &[Instruction]) -> bool {
+    let windows_shell = instructions.iter().any(|ins| match ins {
+        Instruction::Shell(s) => s.arguments.first().is_some_and(|program| {
+            let name = program.value.rsplit(['/', '\\']).next().unwrap_or_default();
+            constants::WINDOWS_SHELLS.contains(&name.to_lowercase().trim_end_matches(".exe"))
         }),
-    }];
+        _ => false,
+    });
+    if windows_shell {
+        return true;
+    }
 
-        let instructions = models::InstructionStats {
-            total_count: 22,
+    final_base_chain
+        .last()
+        .is_some_and(|image| is_windows_image(image))
+}
+
+fn extract_path_modifications(instructions: &[Instruction]) -> Vec<String> {
+    instructions
+        .iter()
+        .filter_map(|ins| match ins {
+            Instruction::Env(e) => parse_utils::parse_kv_instruction(e.arguments.value.as_ref())
+                .remove(constants::PATH),
+            _ => None,
+        })
+        .collect()
+}
+
+// Best effort: the user name is taken to be the first argument of
+// useradd/adduser that is neither an option nor an option's value.
+fn extract_created_users(
+    ordered_instructions: &[models::InstructionEntry],
+    escape: char,
+) -> Vec<String> {
+    let mut users: Vec<String> = vec![];
+    let runs = ordered_instructions
+        .iter()
+        .filter(|ins| ins.keyword == constants::RUN);
+    for ins in runs {
+        for stage in parse_utils::run_pipelines(&ins.arguments, escape)
+            .iter()
+            .flatten()
+        {
+            let tokens = parse_utils::stage_tokens(stage);
+            let Some(program) = parse_utils::stage_program(&tokens) else {
+                continue;
+            };
+            if !constants::USER_CREATION_COMMANDS.contains(&program) {
+                continue;
+            }
+            let mut args = tokens.iter().skip_while(|t| *t == "sudo").skip(1);
+            while let Some(arg) = args.next() {
+                if constants::USER_CREATION_VALUE_OPTIONS.contains(&arg.as_str()) {
+                    args.next();
+                } else if !arg.starts_with('-') {
+                    if !users.contains(arg) {
+                        users.push(arg.clone());
+                    }
+                    break;
+                }
+            }
+        }
+    }
+
+    users
+}
+
+fn extract_file_metrics(body: &str) -> models::FileMetrics {
+    let mut metrics = models::FileMetrics::default();
+    for line in body.lines() {
+        let line = line.trim();
+        metrics.total_lines += 1;
+        if line.is_empty() {
+            metrics.blank_lines += 1;
+        } else if line.starts_with('#') {
+            metrics.comment_lines += 1;
+        } else {
+            metrics.instruction_lines += 1;
+        }
+    }
+
+    metrics
+}
+
+fn extract_flags_used(instructions: &[Instruction]) -> HashMap<String, u32> {
+    let mut flags_used = HashMap::new();
+    for ins in instructions {
+        let flags: &dyn HasOptions = match ins {
+            Instruction::Add(a) => a,
+            Instruction::Copy(c) => c,
+            Instruction::From(f) => f,
+            Instruction::Healthcheck(h) => h,
+            Instruction::Run(r) => r,
+            _ => continue,
+        };
+        for flag in flags.options() {
+            *flags_used
+                .entry(flag.name.value.to_lowercase())
+                .or_default() += 1;
+        }
+    }
+
+    flags_used
+}
+
+fn extract_instructions(
+    instructions: &[Instruction],
+    stages: &[Stage],
+    per_stage: bool,
+) -> models::InstructionStats {
+    let mut by_type = HashMap::new();
+    for ins in instructions {
+        let s = instruction_keyword(ins).to_string();
+        *by_type.entry(s).or_insert(0) += 1;
+    }
+
+    models::InstructionStats {
+        total_count: instructions.len() as u32,
+        by_type,
+        by_type_per_stage: per_stage.then(|| {
+            stages
+                .iter()
+                .map(|stage| {
+                    let mut counts = HashMap::from([(constants::FROM_UC.to_string(), 1)]);
+                    for ins in stage.instructions {
+                        *counts
+                            .entry(instruction_keyword(ins).to_string())
+                            .or_insert(0) += 1;
+                    }
+                    counts
+                })
+                .collect()
+        }),
+    }
+}
+
+fn extract_ports(instructions: &[Instruction]) -> BTreeSet<String> {
+    let mut all_ports = BTreeSet::new();
+    for ins in instructions {
+        let mut ports = match ins {
+            Instruction::Expose(exp) => exp.arguments.iter().map(|x| x.value.to_string()).collect(),
+            _ => BTreeSet::new(),
+        };
+        all_ports.append(&mut ports);
+    }
+
+    all_ports
+}
+
+// Expands EXPOSE values into individual ports. Values that are not valid
+// port numbers (including "$PORT"), reversed ranges and ranges over the cap
+// are left out; the lints report everything but the variables.
+fn extract_parsed_ports(instructions: &[Instruction]) -> Vec<models::ExposedPort> {
+    let mut parsed_ports = BTreeSet::new();
+    for ins in instructions {
+        let Instruction::Expose(exp) = ins else {
+            continue;
+        };
+        for arg in &exp.arguments {
+            let (ports, protocol) = parse_utils::split_port_protocol(&arg.value);
+            let Some(range) = parse_utils::parse_port_range(ports) else {
+                continue;
+            };
+            if range.len() > constants::MAX_EXPOSE_RANGE {
+                continue;
+            }
+            parsed_ports.extend(
+                range
+                    .filter(|&port| port != 0)
+                    .map(|port| models::ExposedPort {
+                        port,
+                        protocol: protocol.clone(),
+                    }),
+            );
+        }
+    }
+
+    parsed_ports.into_iter().collect()
+}
+
+// Stage names are case-insensitive, so references to a stage are lowercased.
+// Anything else is an external image and keeps its case, since registry
+// paths and tags are case-sensitive.
+fn normalize_reference(reference: &str, stage_names: &BTreeSet<String>) -> String {
+    let lc = reference.to_lowercase();
+    match stage_names.contains(&lc) {
+        true => lc,
+        false => reference.to_string(),
+    }
+}
+
+fn normalize_image(stage: &Stage, stage_names: &BTreeSet<String>) -> String {
+    normalize_reference(&stage.from.image.value, stage_names)
+}
+
+fn extract_stage_names(stages: &[Stage]) -> BTreeSet<String> {
+    stages
+        .iter()
+        .filter_map(|s| s.from.as_.as_ref())
+        .map(|stage_name| stage_name.1.value.to_string().to_lowercase())
+        .collect()
+}
+
+// The stage at `start` and every stage it transitively builds on or copies from.
+fn reachable_stages(stages: &[models::StageAnalysis], start: usize) -> BTreeSet<usize> {
+    let mut reachable = BTreeSet::from([start]);
+    let mut pending = vec![start];
+    while let Some(index) = pending.pop() {
+        for &dep in &stages[index].depends_on {
+            if reachable.insert(dep) {
+                pending.push(dep);
+            }
+        }
+    }
+
+    reachable
+}
+
+// Named stages the final stage does not reach, directly or through other
+// stages, so deleting them does not change the built image.
+fn extract_removable_stages(stages: &[models::StageAnalysis]) -> Vec<String> {
+    let Some(final_stage) = stages.last() else {
+        return vec![];
+    };
+    let reachable = reachable_stages(stages, final_stage.index);
+    stages
+        .iter()
+        .filter(|s| !reachable.contains(&s.index))
+        .filter_map(|s| s.name.clone())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+// The lowercased stage names in their sorted order, each spelled as in the
+// first AS clause that declares it.
+fn original_stage_names(stages: &[Stage], stage_names: &BTreeSet<String>) -> Vec<String> {
+    stage_names
+        .iter()
+        .map(|lc| {
+            stages
+                .iter()
+                .filter_map(|s| s.from.as_.as_ref())
+                .map(|stage_name| stage_name.1.value.to_string())
+                .find(|name| name.to_lowercase() == *lc)
+                .unwrap_or_else(|| lc.clone())
+        })
+        .collect()
+}
+
+fn extract_stage_info(stages: &[Stage]) -> (BTreeSet<String>, BTreeSet<String>) {
+    let stage_names = extract_stage_names(stages);
+    let images = stages
+        .iter()
+        .map(|stage| normalize_image(stage, &stage_names))
+        .collect();
+
+    (images, stage_names)
+}
+
+pub(crate) fn is_external_reference(reference: &str, stage_names: &BTreeSet<String>) -> bool {
+    !stage_names.contains(reference)
+        && reference.parse::<usize>().is_err()
+        && reference != constants::SCRATCH
+        && !reference.contains('$')
+}
+
+// Registries of the external images pulled by FROM and by COPY/ADD --from.
+// Stage references, numeric stage indices, scratch and references built
+// from build args are skipped; images without a registry come from Docker Hub.
+fn extract_registries<'a>(
+    references: impl Iterator<Item = &'a String>,
+    stage_names: &BTreeSet<String>,
+) -> Vec<String> {
+    references
+        .filter(|r| is_external_reference(r, stage_names))
+        .filter_map(|r| DockerImage::parse(r).ok())
+        .map(|image| {
+            image
+                .registry
+                .unwrap_or_else(|| constants::DOCKER_HUB.to_string())
+        })
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+fn extract_from_references(
+    instructions: &[Instruction],
+    stage_names: &BTreeSet<String>,
+) -> (BTreeSet<String>, BTreeSet<String>) {
+    let mut copy_from_stages = BTreeSet::new();
+    let mut add_from_stages = BTreeSet::new();
+
+    for ins in instructions {
+        let from_val = match ins {
+            Instruction::Copy(c) => get_from_flag_val(c),
+            Instruction::Add(a) => get_from_flag_val(a),
+            _ => continue,
+        };
+
+        if let Some(val) = from_val {
+            let target_set = match ins {
+                Instruction::Copy(_) => &mut copy_from_stages,
+                Instruction::Add(_) => &mut add_from_stages,
+                _ => unreachable!(),
+            };
+            target_set.insert(normalize_reference(&val, stage_names));
+        }
+    }
+
+    (copy_from_stages, add_from_stages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::vec;
+
+    // Counts the allocations made on each thread, so a test can compare what
+    // an analysis allocates under different options.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            unsafe { System.realloc(ptr, layout, new_size) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn count_allocations<T>(f: impl FnOnce() -> T) -> usize {
+        let before = ALLOCATIONS.with(Cell::get);
+        let result = f();
+        let count = ALLOCATIONS.with(Cell::get) - before;
+        drop(result);
+        count
+    }
+
+    const MULTISTAGE_DOCKERFILE: &str = r#"
+FROM docker.abc.com/base-images/python:3.13-debian@sha256:55f1d15ef4c37870e23c03e89ad238940b55c8ede9f13fac4b7d71c7955f1053 AS base
+
+LABEL org.opencontainers.image.title="My App" \
+      org.opencontainers.image.version="1.0" \
+      org.opencontainers.image.authors="john@example.com"
+
+ENV PYTHONPATH=/src \
+    PYTHONUNBUFFERED=1 \
+    REQUESTS_CA_BUNDLE=/etc/ssl/certs/ca-certificates.crt \
+    PATH="/home/appuser/.local/bin:\$PATH"
+WORKDIR /src
+USER root:root
+
+RUN apt-get update && \
+    apt-get install --no-install-recommends -y postgresql-client curl git && \
+    apt-get autoremove -y && \
+    apt-get clean && \
+    rm -rf /var/lib/apt/lists/*
+
+RUN pip install --no-cache-dir --upgrade pip
+COPY --chown=1000:1000 requirements.txt ./
+RUN pip install --no-cache-dir -r requirements.txt
+
+FROM base AS test
+COPY --chown=1000:1000 test-requirements.txt ./
+USER 1000:1000
+RUN pip install --user --no-cache-dir -r test-requirements.txt
+COPY ./app ./app
+COPY ./test ./test
+
+FROM base
+COPY --chown=1000:1000 ./app ./app
+USER 1000:1000
+ARG GIT_COMMIT
+ENV GIT_COMMIT=\$GIT_COMMIT
+EXPOSE 5000
+
+CMD ["uvicorn", "--host", "0.0.0.0", "--port", "5000", "app.main:app"]"#;
+
+    const MULTISTAGE_COPY_AND_ADD_DOCKERFILE: &str = r#"
+# Stage 1: Build dependencies and tools
+FROM node:20-alpine AS dependencies
+WORKDIR /app
+COPY package*.json ./
+RUN npm ci --only=production && \
+    npm cache clean --force
+
+# Stage 2: Build the application
+FROM node:20-alpine AS builder
+WORKDIR /app
+COPY package*.json ./
+RUN npm ci
+COPY src/ ./src/
+COPY public/ ./public/
+COPY tsconfig.json ./
+RUN npm run build
+
+# Stage 3: Create configuration and assets
+FROM alpine:3.18 AS config-builder
+WORKDIR /configs
+RUN echo "server.port=8080" > app.properties && \
+    echo "database.host=localhost" >> app.properties && \
+    echo "Generated config" > app.conf && \
+    mkdir -p assets && \
+    echo "Asset file content" > assets/data.txt
+
+# Stage 4: Final production image
+FROM node:20-alpine AS production
+WORKDIR /app
+
+# Create non-root user
+RUN addgroup -g 1001 -S nodejs && \
+    adduser -S nextjs -u 1001
+
+# Copy production dependencies from stage 1 using COPY --from
+COPY --from=dependencies /app/node_modules ./node_modules
+
+# Copy built application from stage 2 using COPY --from
+COPY --from=builder /app/dist ./dist
+COPY --from=builder /app/public ./public
+
+# Copy configuration files from stage 3 using ADD --from
+# Note: ADD --from can be used similarly to COPY --from
+ADD --from=config-builder /configs/app.properties ./config/
+ADD --from=config-builder /configs/app.conf ./config/
+ADD --from=config-builder /configs/assets ./assets/
+
+# Copy application files
+COPY package*.json ./
+COPY server.js ./
+
+# Set ownership
+RUN chown -R nextjs:nodejs /app
+USER nextjs
+
+# Expose port
+EXPOSE 8080
+
+# Health check
+HEALTHCHECK --interval=30s --timeout=3s --start-period=5s --retries=3 \
+    CMD curl -f http://localhost:8080/health || exit 1
+
+# Start the application
+CMD ["node", "server.js"]
+"#;
+
+    const SINGLE_STAGE_DOCKERFILE: &str = r#"
+FROM node:20-alpine
+
+# Set working directory
+WORKDIR /app
+
+# Copy package files
+COPY package*.json ./
+
+# Install dependencies
+RUN npm install
+
+# Copy application source code
+COPY . .
+
+# Create non-root user
+RUN addgroup -g 1001 -S nodejs && \
+    adduser -S nextjs -u 1001
+
+# Change ownership of the app directory
+RUN chown -R nextjs:nodejs /app
+
+# Switch to non-root user
+USER nextjs
+
+# Expose port
+EXPOSE 3000
+
+# Set environment variable
+ENV NODE_ENV=production
+
+# Start the application
+CMD ["npm", "start"]
+"#;
+
+    #[test]
+    fn test_multistage() {
+        let dockerfile = MULTISTAGE_DOCKERFILE;
+        let msa = models::MultistageAnalysis {
+            is_multistage: true,
+            has_multiple_stages: true,
+            stages_used_as_base_images: vec!["base".to_string()],
+            stages_copied_from: vec![],
+            stages_added_from: vec![],
+            unused_stages: vec!["test".to_string()],
+            artifact_only_stages: vec![],
+        };
+        let images: Vec<models::Image> = vec![models::Image {
+            full: "base".to_string(),
+            components: Some(models::ImageComponents {
+                registry: None,
+                name: "base".to_string(),
+                tag: None,
+                digest: None,
+            }),
+        }, models::Image {
+        full: "docker.abc.com/base-images/python:3.13-debian@sha256:55f1d15ef4c37870e23c03e89ad238940b55c8ede9f13fac4b7d71c7955f1053".to_string(),
+        components: Some(models::ImageComponents {
+            registry: Some("docker.abc.com".to_string()),
+            name: "base-images/python".to_string(),
+            tag: Some("3.13-debian".to_string()),
+            digest: Some("sha256:55f1d15ef4c37870e23c03e89ad238940b55c8ede9f13fac4b7d71c7955f1053".to_string()),
+        }),
+    }];
+
+        let instructions = models::InstructionStats {
+            total_count: 22,
+            by_type: HashMap::from([
+                ("ARG".to_string(), 1),
+                ("CMD".to_string(), 1),
+                ("COPY".to_string(), 5),
+                ("ENV".to_string(), 2),
+                ("EXPOSE".to_string(), 1),
+                ("FROM".to_string(), 3),
+                ("LABEL".to_string(), 1),
+                ("RUN".to_string(), 4),
+                ("USER".to_string(), 3),
+                ("WORKDIR".to_string(), 1),
+            ]),
+            by_type_per_stage: None,
+        };
+
+        let env_vars = HashMap::from([
+            ("PYTHONPATH".into(), "/src".into()),
+            ("PYTHONUNBUFFERED".into(), "1".into()),
+            (
+                "REQUESTS_CA_BUNDLE".into(),
+                "/etc/ssl/certs/ca-certificates.crt".into(),
+            ),
+            ("PATH".into(), "/home/appuser/.local/bin:$PATH".into()),
+            ("GIT_COMMIT".into(), "$GIT_COMMIT".into()),
+        ]);
+
+        let args = HashMap::from([("GIT_COMMIT".into(), None)]);
+        let labels = HashMap::from([
+            ("org.opencontainers.image.title".into(), "My App".into()),
+            ("org.opencontainers.image.version".into(), "1.0".into()),
+            (
+                "org.opencontainers.image.authors".into(),
+                "john@example.com".into(),
+            ),
+        ]);
+
+        let expected = models::Analysis {
+            num_stages: 3,
+            stage_names: vec!["base".to_string(), "test".to_string()],
+            images,
+            copy_from_stages: vec![],
+            add_from_stages: vec![],
+            multistage_analysis: msa,
+            exposed_ports: vec!["5000".to_string()],
+            instructions,
+            args,
+            labels,
+            env_vars,
+            escape_char: '\\',
+            final_base_chain: vec![
+                "base".to_string(),
+                "docker.abc.com/base-images/python:3.13-debian@sha256:55f1d15ef4c37870e23c03e89ad238940b55c8ede9f13fac4b7d71c7955f1053".to_string(),
+            ],
+            distinct_base_images: 1,
+            digest_pinned_images: vec![
+                "docker.abc.com/base-images/python:3.13-debian@sha256:55f1d15ef4c37870e23c03e89ad238940b55c8ede9f13fac4b7d71c7955f1053".to_string(),
+            ],
+            registries: vec!["docker.abc.com".to_string()],
+            removable_stages: vec!["test".to_string()],
+            stages: vec![
+                models::StageAnalysis {
+                    index: 0,
+                    name: Some("base".to_string()),
+                    base_image: "docker.abc.com/base-images/python:3.13-debian@sha256:55f1d15ef4c37870e23c03e89ad238940b55c8ede9f13fac4b7d71c7955f1053".to_string(),
+                    args: HashMap::new(),
+                    labels: HashMap::from([
+                        (
+                            "org.opencontainers.image.authors".to_string(),
+                            "john@example.com".to_string(),
+                        ),
+                        ("org.opencontainers.image.title".to_string(), "My App".to_string()),
+                        ("org.opencontainers.image.version".to_string(), "1.0".to_string()),
+                    ]),
+                    env_vars: HashMap::from([
+                        ("PATH".to_string(), "/home/appuser/.local/bin:$PATH".to_string()),
+                        ("PYTHONPATH".to_string(), "/src".to_string()),
+                        ("PYTHONUNBUFFERED".to_string(), "1".to_string()),
+                        (
+                            "REQUESTS_CA_BUNDLE".to_string(),
+                            "/etc/ssl/certs/ca-certificates.crt".to_string(),
+                        ),
+                    ]),
+                    depends_on: vec![],
+                    platform: None,
+                    user: Some("root:root".to_string()),
+                    exposed_ports: vec![],
+                    entrypoint: None,
+                },
+                models::StageAnalysis {
+                    index: 1,
+                    name: Some("test".to_string()),
+                    base_image: "base".to_string(),
+                    args: HashMap::new(),
+                    labels: HashMap::new(),
+                    env_vars: HashMap::new(),
+                    depends_on: vec![0],
+                    platform: None,
+                    user: Some("1000:1000".to_string()),
+                    exposed_ports: vec![],
+                    entrypoint: None,
+                },
+                models::StageAnalysis {
+                    index: 2,
+                    name: None,
+                    base_image: "base".to_string(),
+                    args: HashMap::from([("GIT_COMMIT".to_string(), None)]),
+                    labels: HashMap::new(),
+                    env_vars: HashMap::from([
+                        ("GIT_COMMIT".to_string(), "$GIT_COMMIT".to_string()),
+                    ]),
+                    depends_on: vec![0],
+                    platform: None,
+                    user: Some("1000:1000".to_string()),
+                    exposed_ports: vec!["5000".to_string()],
+                    entrypoint: None,
+                },
+            ],
+            parsed_ports: vec![
+                models::ExposedPort {
+                    port: 5000,
+                    protocol: "tcp".to_string(),
+                },
+            ],
+            flags_used: HashMap::from([("chown".to_string(), 3)]),
+            file_metrics: models::FileMetrics {
+                total_lines: 39,
+                comment_lines: 0,
+                blank_lines: 8,
+                instruction_lines: 31,
+            },
+            per_stage_distinct_instruction_kinds: vec![7, 4, 7],
+            ordered_instructions: vec![
+                models::InstructionEntry {
+                    keyword: "FROM".to_string(),
+                    arguments: "docker.abc.com/base-images/python:3.13-debian@sha256:55f1d15ef4c37870e23c03e89ad238940b55c8ede9f13fac4b7d71c7955f1053 AS base".to_string(),
+                    stage_index: 0,
+                    line: 2,
+                },
+                models::InstructionEntry {
+                    keyword: "LABEL".to_string(),
+                    arguments: "org.opencontainers.image.title=\"My App\" \\\n      org.opencontainers.image.version=\"1.0\" \\\n      org.opencontainers.image.authors=\"john@example.com\"".to_string(),
+                    stage_index: 0,
+                    line: 4,
+                },
+                models::InstructionEntry {
+                    keyword: "ENV".to_string(),
+                    arguments: "PYTHONPATH=/src \\\n    PYTHONUNBUFFERED=1 \\\n    REQUESTS_CA_BUNDLE=/etc/ssl/certs/ca-certificates.crt \\\n    PATH=\"/home/appuser/.local/bin:\\$PATH\"".to_string(),
+                    stage_index: 0,
+                    line: 8,
+                },
+                models::InstructionEntry {
+                    keyword: "WORKDIR".to_string(),
+                    arguments: "/src".to_string(),
+                    stage_index: 0,
+                    line: 12,
+                },
+                models::InstructionEntry {
+                    keyword: "USER".to_string(),
+                    arguments: "root:root".to_string(),
+                    stage_index: 0,
+                    line: 13,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "apt-get update && \\\n    apt-get install --no-install-recommends -y postgresql-client curl git && \\\n    apt-get autoremove -y && \\\n    apt-get clean && \\\n    rm -rf /var/lib/apt/lists/*".to_string(),
+                    stage_index: 0,
+                    line: 15,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "pip install --no-cache-dir --upgrade pip".to_string(),
+                    stage_index: 0,
+                    line: 21,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "--chown=1000:1000 requirements.txt ./".to_string(),
+                    stage_index: 0,
+                    line: 22,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "pip install --no-cache-dir -r requirements.txt".to_string(),
+                    stage_index: 0,
+                    line: 23,
+                },
+                models::InstructionEntry {
+                    keyword: "FROM".to_string(),
+                    arguments: "base AS test".to_string(),
+                    stage_index: 1,
+                    line: 25,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "--chown=1000:1000 test-requirements.txt ./".to_string(),
+                    stage_index: 1,
+                    line: 26,
+                },
+                models::InstructionEntry {
+                    keyword: "USER".to_string(),
+                    arguments: "1000:1000".to_string(),
+                    stage_index: 1,
+                    line: 27,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "pip install --user --no-cache-dir -r test-requirements.txt".to_string(),
+                    stage_index: 1,
+                    line: 28,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "./app ./app".to_string(),
+                    stage_index: 1,
+                    line: 29,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "./test ./test".to_string(),
+                    stage_index: 1,
+                    line: 30,
+                },
+                models::InstructionEntry {
+                    keyword: "FROM".to_string(),
+                    arguments: "base".to_string(),
+                    stage_index: 2,
+                    line: 32,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "--chown=1000:1000 ./app ./app".to_string(),
+                    stage_index: 2,
+                    line: 33,
+                },
+                models::InstructionEntry {
+                    keyword: "USER".to_string(),
+                    arguments: "1000:1000".to_string(),
+                    stage_index: 2,
+                    line: 34,
+                },
+                models::InstructionEntry {
+                    keyword: "ARG".to_string(),
+                    arguments: "GIT_COMMIT".to_string(),
+                    stage_index: 2,
+                    line: 35,
+                },
+                models::InstructionEntry {
+                    keyword: "ENV".to_string(),
+                    arguments: "GIT_COMMIT=\\$GIT_COMMIT".to_string(),
+                    stage_index: 2,
+                    line: 36,
+                },
+                models::InstructionEntry {
+                    keyword: "EXPOSE".to_string(),
+                    arguments: "5000".to_string(),
+                    stage_index: 2,
+                    line: 37,
+                },
+                models::InstructionEntry {
+                    keyword: "CMD".to_string(),
+                    arguments: "[\"uvicorn\", \"--host\", \"0.0.0.0\", \"--port\", \"5000\", \"app.main:app\"]".to_string(),
+                    stage_index: 2,
+                    line: 39,
+                },
+            ],
+            copy_operations: vec![
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["requirements.txt".to_string()],
+                    destination: "./".to_string(),
+                    stage_index: 0,
+                    line: 22,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["test-requirements.txt".to_string()],
+                    destination: "./".to_string(),
+                    stage_index: 1,
+                    line: 26,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["./app".to_string()],
+                    destination: "./app".to_string(),
+                    stage_index: 1,
+                    line: 29,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["./test".to_string()],
+                    destination: "./test".to_string(),
+                    stage_index: 1,
+                    line: 30,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["./app".to_string()],
+                    destination: "./app".to_string(),
+                    stage_index: 2,
+                    line: 33,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+            ],
+            run_operations: vec![
+                models::RunOperation {
+                    command: "apt-get update && \\\n    apt-get install --no-install-recommends -y postgresql-client curl git && \\\n    apt-get autoremove -y && \\\n    apt-get clean && \\\n    rm -rf /var/lib/apt/lists/*".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 0,
+                    line: 15,
+                },
+                models::RunOperation {
+                    command: "pip install --no-cache-dir --upgrade pip".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 0,
+                    line: 21,
+                },
+                models::RunOperation {
+                    command: "pip install --no-cache-dir -r requirements.txt".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 0,
+                    line: 23,
+                },
+                models::RunOperation {
+                    command: "pip install --user --no-cache-dir -r test-requirements.txt".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 1,
+                    line: 28,
+                },
+            ],
+            arg_usages: HashMap::from([("GIT_COMMIT".to_string(), vec!["ENV".to_string()])]),
+            required_build_args: vec!["GIT_COMMIT".to_string()],
+            env_count: 5,
+            arg_count: 1,
+            label_count: 3,
+            effective_env: HashMap::from([
+                ("GIT_COMMIT".to_string(), "$GIT_COMMIT".to_string()),
+                ("PATH".to_string(), "/home/appuser/.local/bin:$PATH".to_string()),
+                ("PYTHONPATH".to_string(), "/src".to_string()),
+                ("PYTHONUNBUFFERED".to_string(), "1".to_string()),
+                (
+                    "REQUESTS_CA_BUNDLE".to_string(),
+                    "/etc/ssl/certs/ca-certificates.crt".to_string(),
+                ),
+            ]),
+            path_modifications: vec!["/home/appuser/.local/bin:$PATH".to_string()],
+            quality_score: 100,
+            ..Default::default()
+        };
+
+        let res = analyze_dockerfile(dockerfile);
+        assert!(res.is_ok());
+        let analysis = res.unwrap();
+        assert_eq!(analysis, expected);
+        assert_eq!(analysis.env_count, 5);
+        assert_eq!(analysis.arg_count, 1);
+        assert_eq!(analysis.label_count, 3);
+
+        let env_keys = |index: usize| -> BTreeSet<&str> {
+            analysis.stages[index]
+                .env_vars
+                .keys()
+                .map(String::as_str)
+                .collect()
+        };
+        assert_eq!(
+            env_keys(0),
+            BTreeSet::from([
+                "PATH",
+                "PYTHONPATH",
+                "PYTHONUNBUFFERED",
+                "REQUESTS_CA_BUNDLE"
+            ])
+        );
+        assert_eq!(env_keys(2), BTreeSet::from(["GIT_COMMIT"]));
+        assert_eq!(analysis.stages[0].labels.len(), 3);
+        assert!(analysis.stages[2].labels.is_empty());
+    }
+
+    #[test]
+    fn test_preserve_stage_case() {
+        let dockerfile = "FROM golang:1.22 AS Builder\nRUN go build -o /app\n\nFROM builder AS Test\n\nFROM alpine\nCOPY --from=BUILDER /app /app\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(analysis.stage_names, vec!["builder", "test"]);
+
+        let options = models::AnalysisOptions {
+            preserve_stage_case: true,
+            ..Default::default()
+        };
+        let preserved = super::analyze_dockerfile_with_options(dockerfile, &options).unwrap();
+        assert_eq!(preserved.stage_names, vec!["Builder", "Test"]);
+        assert_eq!(preserved.copy_from_stages, vec!["builder"]);
+        assert_eq!(preserved.multistage_analysis, analysis.multistage_analysis);
+        assert_eq!(preserved.external_images(), analysis.external_images());
+        assert_eq!(
+            preserved.stage_base_references(),
+            analysis.stage_base_references()
+        );
+        assert_eq!(preserved.stage_base_references().len(), 1);
+        assert_eq!(preserved.external_images().len(), 2);
+    }
+
+    #[test]
+    fn test_removable_stages() {
+        let dockerfile =
+            "FROM alpine AS build\nRUN make\n\nFROM alpine AS final\nCOPY --from=build /out /out\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(
+            analysis.multistage_analysis.unused_stages,
+            vec!["final".to_string()]
+        );
+        assert!(analysis.removable_stages.is_empty());
+
+        let dockerfile = "FROM alpine AS deps\nFROM deps AS lint\nFROM alpine AS docs\nCOPY --from=lint /report /report\nFROM deps AS Release\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(analysis.removable_stages, vec!["docs", "lint"]);
+
+        let analysis = analyze_dockerfile(MULTISTAGE_DOCKERFILE).unwrap();
+        assert_eq!(analysis.removable_stages, vec!["test"]);
+    }
+
+    #[test]
+    fn test_analyze_dockerfile_bytes() {
+        let options = models::AnalysisOptions::default();
+        let data = [b"\xef\xbb\xbf".as_slice(), MULTISTAGE_DOCKERFILE.as_bytes()].concat();
+        assert_eq!(
+            super::analyze_dockerfile_bytes(&data, &options).unwrap(),
+            analyze_dockerfile(MULTISTAGE_DOCKERFILE).unwrap()
+        );
+
+        let data = b"FROM alpine:3.20\nLABEL description=\"caf\xe9\"\nCMD [\"sh\"]\n";
+        let analysis = super::analyze_dockerfile_bytes(data, &options).unwrap();
+        assert_eq!(analysis.labels["description"], "caf\u{fffd}");
+        let finding = &analysis.findings[0];
+        assert_eq!(finding.rule_id, "invalid-utf8");
+        assert_eq!(finding.severity, constants::SEVERITY_WARNING);
+        assert_eq!(finding.line, Some(2));
+        assert!(analysis.quality_deductions.contains_key("invalid-utf8"));
+
+        assert_eq!(
+            super::analyze_dockerfile_bytes(b"\xff\xfe", &options).unwrap_err(),
+            AnalyzeError::ParseError(
+                "unknown instruction '\u{fffd}\u{fffd}' at line 1 column 1".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parser_directive_fields() {
+        let dockerfile = "# syntax=docker/dockerfile:1\n# escape=`\nFROM mcr.microsoft.com/windows/servercore:ltsc2022\nRUN dir `\n    c:\\\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(analysis.escape_char, '`');
+        assert_eq!(
+            analysis.syntax_frontend.as_deref(),
+            Some("docker/dockerfile:1")
+        );
+
+        let analysis = analyze_dockerfile("FROM alpine\n").unwrap();
+        assert_eq!(analysis.escape_char, '\\');
+        assert_eq!(analysis.syntax_frontend, None);
+    }
+
+    #[test]
+    fn test_to_report() {
+        let analysis = analyze_dockerfile(MULTISTAGE_DOCKERFILE).unwrap();
+        let report = analysis.to_report();
+        for section in [
+            "Dockerfile analysis\n",
+            "\nStages (3)\n",
+            "\nImages (2)\n",
+            "\nExposed ports (1)\n",
+            "\nFindings (",
+        ] {
+            assert!(report.contains(section), "missing {section:?} in {report}");
+        }
+        assert!(report.contains("  1: test FROM base\n"));
+        assert!(report.contains("  5000\n"));
+        for finding in &analysis.findings {
+            assert!(report.contains(&finding.message));
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let analysis = analyze_dockerfile(MULTISTAGE_DOCKERFILE).unwrap();
+        let summary = analysis.summary();
+        assert_eq!(
+            summary,
+            models::Summary {
+                num_stages: 3,
+                is_multistage: true,
+                num_images: 2,
+                num_exposed_ports: 1,
+                num_findings: analysis.findings.len(),
+                quality_score: analysis.quality_score,
+            }
+        );
+    }
+
+    #[test]
+    fn test_crlf_and_bom_match_lf() {
+        let crlf = format!("\u{feff}{}", MULTISTAGE_DOCKERFILE.replace('\n', "\r\n"));
+        assert_eq!(
+            analyze_dockerfile(&crlf).unwrap(),
+            analyze_dockerfile(MULTISTAGE_DOCKERFILE).unwrap()
+        );
+        assert_eq!(
+            super::analyze_multistage_only(&crlf).unwrap(),
+            super::analyze_multistage_only(MULTISTAGE_DOCKERFILE).unwrap()
+        );
+        let keywords: Result<Vec<_>, _> = super::instruction_keywords(&crlf).unwrap().collect();
+        let expected: Result<Vec<_>, _> = super::instruction_keywords(MULTISTAGE_DOCKERFILE)
+            .unwrap()
+            .collect();
+        assert_eq!(keywords, expected);
+    }
+
+    #[test]
+    fn test_analyze_target() {
+        let options = models::AnalysisOptions::default();
+        let analysis = super::analyze_dockerfile_target(
+            MULTISTAGE_COPY_AND_ADD_DOCKERFILE,
+            "builder",
+            &options,
+        )
+        .unwrap();
+        assert_eq!(analysis.num_stages, 1);
+        assert_eq!(analysis.stage_names, vec!["builder"]);
+        assert!(analysis.exposed_ports.is_empty());
+        assert_eq!(analysis.ordered_instructions[0].line, 10);
+        assert_eq!(
+            analysis.file_metrics,
+            analyze_dockerfile(MULTISTAGE_COPY_AND_ADD_DOCKERFILE)
+                .unwrap()
+                .file_metrics
+        );
+        let crlf = MULTISTAGE_COPY_AND_ADD_DOCKERFILE.replace('\n', "\r\n");
+        let target = super::analyze_dockerfile_target(&crlf, "builder", &options).unwrap();
+        assert_eq!(target.file_metrics, analysis.file_metrics);
+
+        let dockerfile = "ARG GO=1.22\nFROM golang:${GO} AS tools\nFROM golang:${GO} AS base\nFROM base AS builder\nCOPY --from=tools /go/bin /usr/bin\nFROM alpine AS lint\nFROM alpine\nCOPY --from=builder /app /app\n";
+        let analysis = super::analyze_dockerfile_target(dockerfile, "Builder", &options).unwrap();
+        assert_eq!(analysis.stage_names, vec!["base", "builder", "tools"]);
+        assert_eq!(analysis.global_args.len(), 1);
+
+        let err = super::analyze_dockerfile_target(dockerfile, "release", &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Target stage 'release' not found in the Dockerfile"
+        );
+    }
+
+    #[test]
+    fn test_skip_lints() {
+        let options = models::AnalysisOptions {
+            run_lints: false,
+            ..Default::default()
+        };
+        let full = analyze_dockerfile(MULTISTAGE_DOCKERFILE).unwrap();
+        let structural =
+            super::analyze_dockerfile_with_options(MULTISTAGE_DOCKERFILE, &options).unwrap();
+        assert!(!full.run_operations.is_empty());
+        assert!(structural.findings.is_empty());
+        assert!(structural.run_operations.is_empty());
+        assert!(structural.copy_optimization_suggestions.is_empty());
+        assert!(structural.quality_deductions.is_empty());
+        assert!(structural.created_users.is_empty());
+        assert_eq!(structural.stages, full.stages);
+        assert_eq!(structural.parsed_ports, full.parsed_ports);
+        assert_eq!(
+            structural,
+            models::Analysis {
+                run_operations: vec![],
+                ..full
+            }
+        );
+
+        let with_lints = count_allocations(|| analyze_dockerfile(MULTISTAGE_DOCKERFILE));
+        let without_lints = count_allocations(|| {
+            super::analyze_dockerfile_with_options(MULTISTAGE_DOCKERFILE, &options)
+        });
+        // The run_lints documentation claims less than half the allocations.
+        assert!(
+            without_lints * 2 < with_lints,
+            "{without_lints} vs {with_lints}"
+        );
+    }
+
+    #[test]
+    fn test_duplicate_stage_name() {
+        let err =
+            analyze_dockerfile("FROM alpine AS base\nFROM base AS app\nFROM debian AS base\n")
+                .unwrap_err();
+        assert!(matches!(err, AnalyzeError::DuplicateStage(_)));
+        assert!(err.to_string().starts_with("duplicate stage name 'base'"));
+
+        for dockerfile in [
+            "FROM alpine AS base\nFOO bar\nFROM debian AS base\n",
+            "FROM alpine AS x\nFROM debian AS x\nFOO bar\n",
+        ] {
+            let err = analyze_dockerfile(dockerfile).unwrap_err();
+            assert!(matches!(err, AnalyzeError::ParseError(_)), "{err}");
+        }
+    }
+
+    #[test]
+    fn test_healthcheck_without_command() {
+        let dockerfile = "FROM alpine\nHEALTHCHECK --interval=30s --timeout=3s\nCMD [\"sh\"]\n";
+        let err = analyze_dockerfile(dockerfile).unwrap_err();
+        assert!(matches!(err, AnalyzeError::ParseError(_)));
+        assert_eq!(
+            err.to_string(),
+            "HEALTHCHECK at line 2 has no test command; add CMD <command> or use HEALTHCHECK NONE"
+        );
+
+        for dockerfile in [
+            "FROM alpine\nHEALTHCHECK --interval=30s CMD wget -q -O- http://localhost/ || exit 1\n",
+            "FROM alpine\nHEALTHCHECK NONE\n",
+        ] {
+            let analysis = analyze_dockerfile(dockerfile).unwrap();
+            assert_eq!(analysis.instructions.by_type[constants::HEALTHCHECK], 1);
+        }
+    }
+
+    #[test]
+    fn test_instruction_before_from() {
+        let dockerfile =
+            "# syntax=docker/dockerfile:1\nARG VERSION=3.20\nrun echo hi\nFROM alpine:${VERSION}\n";
+        // instruction_before_from relies on the parser's wording.
+        let raw = parse(dockerfile).unwrap_err().to_string();
+        assert!(raw.starts_with("expected FROM at line 3 "), "{raw}");
+        let err = analyze_dockerfile(dockerfile).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "RUN at line 3 comes before the first FROM; only ARG instructions, parser directives and comments may precede FROM"
+        );
+        assert_eq!(
+            super::instruction_before_from("RUN echo hi\n", "expected FROM at line 0 column 1"),
+            None
+        );
+
+        let dockerfile = "# syntax=docker/dockerfile:1\nARG VERSION=3.20\nFROM alpine:${VERSION}\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert!(analysis.findings.iter().all(|f| f.severity != "error"));
+    }
+
+    #[test]
+    fn test_invalid_dockerfile() {
+        let res = analyze_dockerfile("invalid dockerfile content");
+        assert!(res.is_err());
+        let err_text = res.unwrap_err().to_string();
+        assert!(err_text.contains("unknown instruction 'invalid'"));
+    }
+    #[test]
+    fn test_single_stage() {
+        let dockerfile = SINGLE_STAGE_DOCKERFILE;
+        let msa = models::MultistageAnalysis {
+            is_multistage: false,
+            has_multiple_stages: false,
+            stages_used_as_base_images: vec![],
+            stages_copied_from: vec![],
+            stages_added_from: vec![],
+            unused_stages: vec![],
+            artifact_only_stages: vec![],
+        };
+        let images: Vec<models::Image> = vec![models::Image {
+            full: "node:20-alpine".to_string(),
+            components: Some(models::ImageComponents {
+                registry: None,
+                name: "node".to_string(),
+                tag: Some("20-alpine".to_string()),
+                digest: None,
+            }),
+        }];
+
+        let instructions = models::InstructionStats {
+            total_count: 11,
+            by_type: HashMap::from([
+                ("CMD".to_string(), 1),
+                ("COPY".to_string(), 2),
+                ("ENV".to_string(), 1),
+                ("EXPOSE".to_string(), 1),
+                ("FROM".to_string(), 1),
+                ("RUN".to_string(), 3),
+                ("USER".to_string(), 1),
+                ("WORKDIR".to_string(), 1),
+            ]),
+            by_type_per_stage: None,
+        };
+
+        let env_vars = HashMap::from([("NODE_ENV".into(), "production".into())]);
+
+        let expected = models::Analysis {
+            num_stages: 1,
+            stage_names: vec![],
+            images,
+            copy_from_stages: vec![],
+            add_from_stages: vec![],
+            multistage_analysis: msa,
+            exposed_ports: vec!["3000".to_string()],
+            instructions,
+            args: HashMap::new(),
+            labels: HashMap::new(),
+            env_vars,
+            escape_char: '\\',
+            final_base_chain: vec!["node:20-alpine".to_string()],
+            distinct_base_images: 1,
+            registries: vec!["docker.io".to_string()],
+            stages: vec![models::StageAnalysis {
+                index: 0,
+                name: None,
+                base_image: "node:20-alpine".to_string(),
+                args: HashMap::new(),
+                labels: HashMap::new(),
+                env_vars: HashMap::from([("NODE_ENV".to_string(), "production".to_string())]),
+                depends_on: vec![],
+                platform: None,
+                user: Some("nextjs".to_string()),
+                exposed_ports: vec!["3000".to_string()],
+                entrypoint: None,
+            }],
+            parsed_ports: vec![models::ExposedPort {
+                port: 3000,
+                protocol: "tcp".to_string(),
+            }],
+            file_metrics: models::FileMetrics {
+                total_lines: 33,
+                comment_lines: 10,
+                blank_lines: 11,
+                instruction_lines: 12,
+            },
+            per_stage_distinct_instruction_kinds: vec![8],
+            ordered_instructions: vec![
+                models::InstructionEntry {
+                    keyword: "FROM".to_string(),
+                    arguments: "node:20-alpine".to_string(),
+                    stage_index: 0,
+                    line: 2,
+                },
+                models::InstructionEntry {
+                    keyword: "WORKDIR".to_string(),
+                    arguments: "/app".to_string(),
+                    stage_index: 0,
+                    line: 5,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "package*.json ./".to_string(),
+                    stage_index: 0,
+                    line: 8,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "npm install".to_string(),
+                    stage_index: 0,
+                    line: 11,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: ". .".to_string(),
+                    stage_index: 0,
+                    line: 14,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "addgroup -g 1001 -S nodejs && \\\n    adduser -S nextjs -u 1001"
+                        .to_string(),
+                    stage_index: 0,
+                    line: 17,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "chown -R nextjs:nodejs /app".to_string(),
+                    stage_index: 0,
+                    line: 21,
+                },
+                models::InstructionEntry {
+                    keyword: "USER".to_string(),
+                    arguments: "nextjs".to_string(),
+                    stage_index: 0,
+                    line: 24,
+                },
+                models::InstructionEntry {
+                    keyword: "EXPOSE".to_string(),
+                    arguments: "3000".to_string(),
+                    stage_index: 0,
+                    line: 27,
+                },
+                models::InstructionEntry {
+                    keyword: "ENV".to_string(),
+                    arguments: "NODE_ENV=production".to_string(),
+                    stage_index: 0,
+                    line: 30,
+                },
+                models::InstructionEntry {
+                    keyword: "CMD".to_string(),
+                    arguments: "[\"npm\", \"start\"]".to_string(),
+                    stage_index: 0,
+                    line: 33,
+                },
+            ],
+            copy_operations: vec![
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["package*.json".to_string()],
+                    destination: "./".to_string(),
+                    stage_index: 0,
+                    line: 8,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec![".".to_string()],
+                    destination: ".".to_string(),
+                    stage_index: 0,
+                    line: 14,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+            ],
+            run_operations: vec![
+                models::RunOperation {
+                    command: "npm install".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 0,
+                    line: 11,
+                },
+                models::RunOperation {
+                    command: "addgroup -g 1001 -S nodejs && \\\n    adduser -S nextjs -u 1001"
+                        .to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 0,
+                    line: 17,
+                },
+                models::RunOperation {
+                    command: "chown -R nextjs:nodejs /app".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 0,
+                    line: 21,
+                },
+            ],
+            created_users: vec!["nextjs".to_string()],
+            env_count: 1,
+            effective_env: HashMap::from([("NODE_ENV".to_string(), "production".to_string())]),
+            quality_score: 100,
+            ..Default::default()
+        };
+        let res = analyze_dockerfile(dockerfile);
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_ordered_instructions_follow_file_order() {
+        let res = analyze_dockerfile(SINGLE_STAGE_DOCKERFILE);
+        assert!(res.is_ok());
+        let ordered: Vec<(String, String, usize)> = res
+            .unwrap()
+            .ordered_instructions
+            .into_iter()
+            .map(|ins| (ins.keyword, ins.arguments, ins.stage_index))
+            .collect();
+        let expected: Vec<(String, String, usize)> = [
+            ("FROM", "node:20-alpine"),
+            ("WORKDIR", "/app"),
+            ("COPY", "package*.json ./"),
+            ("RUN", "npm install"),
+            ("COPY", ". ."),
+            (
+                "RUN",
+                "addgroup -g 1001 -S nodejs && \\\n    adduser -S nextjs -u 1001",
+            ),
+            ("RUN", "chown -R nextjs:nodejs /app"),
+            ("USER", "nextjs"),
+            ("EXPOSE", "3000"),
+            ("ENV", "NODE_ENV=production"),
+            ("CMD", r#"["npm", "start"]"#),
+        ]
+        .into_iter()
+        .map(|(k, a)| (k.to_string(), a.to_string(), 0))
+        .collect();
+        assert_eq!(ordered, expected);
+    }
+
+    #[test]
+    fn test_ordered_instructions_stage_index() {
+        let dockerfile = r#"
+ARG BASE=alpine:3.18
+FROM $BASE AS builder
+RUN make
+FROM scratch
+COPY --from=builder /out /
+"#;
+        let res = analyze_dockerfile(dockerfile);
+        assert!(res.is_ok());
+        let stage_indexes: Vec<(String, usize)> = res
+            .unwrap()
+            .ordered_instructions
+            .into_iter()
+            .map(|ins| (ins.keyword, ins.stage_index))
+            .collect();
+        assert_eq!(
+            stage_indexes,
+            vec![
+                ("ARG".to_string(), 0),
+                ("FROM".to_string(), 0),
+                ("RUN".to_string(), 0),
+                ("FROM".to_string(), 1),
+                ("COPY".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multistage_with_copy_and_add() {
+        let dockerfile = MULTISTAGE_COPY_AND_ADD_DOCKERFILE;
+
+        let msa = models::MultistageAnalysis {
+            is_multistage: true,
+            has_multiple_stages: true,
+            stages_used_as_base_images: vec![],
+            stages_copied_from: vec!["builder".to_string(), "dependencies".to_string()],
+            stages_added_from: vec!["config-builder".to_string()],
+            unused_stages: vec!["production".to_string()],
+            artifact_only_stages: vec![
+                "builder".to_string(),
+                "config-builder".to_string(),
+                "dependencies".to_string(),
+            ],
+        };
+        let images: Vec<models::Image> = vec![
+            models::Image {
+                full: "alpine:3.18".to_string(),
+                components: Some(models::ImageComponents {
+                    registry: None,
+                    name: "alpine".to_string(),
+                    tag: Some("3.18".to_string()),
+                    digest: None,
+                }),
+            },
+            models::Image {
+                full: "node:20-alpine".to_string(),
+                components: Some(models::ImageComponents {
+                    registry: None,
+                    name: "node".to_string(),
+                    tag: Some("20-alpine".to_string()),
+                    digest: None,
+                }),
+            },
+        ];
+        let instructions = models::InstructionStats {
+            total_count: 31,
+            by_type: HashMap::from([
+                ("ADD".to_string(), 3),
+                ("CMD".to_string(), 1),
+                ("COPY".to_string(), 10),
+                ("EXPOSE".to_string(), 1),
+                ("FROM".to_string(), 4),
+                ("HEALTHCHECK".to_string(), 1),
+                ("RUN".to_string(), 6),
+                ("USER".to_string(), 1),
+                ("WORKDIR".to_string(), 4),
+            ]),
+            by_type_per_stage: None,
+        };
+
+        let expected = models::Analysis {
+            num_stages: 4,
+            stage_names: vec![
+                "builder".to_string(),
+                "config-builder".to_string(),
+                "dependencies".to_string(),
+                "production".to_string(),
+            ],
+            images,
+            copy_from_stages: vec!["builder".to_string(), "dependencies".to_string()],
+            add_from_stages: vec!["config-builder".to_string()],
+            multistage_analysis: msa,
+            exposed_ports: vec!["8080".to_string()],
+            instructions,
+            args: HashMap::new(),
+            labels: HashMap::new(),
+            env_vars: HashMap::new(),
+            escape_char: '\\',
+            final_base_chain: vec!["node:20-alpine".to_string()],
+            distinct_base_images: 2,
+            reused_base_images: vec!["node:20-alpine".to_string()],
+            registries: vec!["docker.io".to_string()],
+            stages: vec![
+                models::StageAnalysis {
+                    index: 0,
+                    name: Some("dependencies".to_string()),
+                    base_image: "node:20-alpine".to_string(),
+                    args: HashMap::new(),
+                    labels: HashMap::new(),
+                    env_vars: HashMap::new(),
+                    depends_on: vec![],
+                    platform: None,
+                    user: None,
+                    exposed_ports: vec![],
+                    entrypoint: None,
+                },
+                models::StageAnalysis {
+                    index: 1,
+                    name: Some("builder".to_string()),
+                    base_image: "node:20-alpine".to_string(),
+                    args: HashMap::new(),
+                    labels: HashMap::new(),
+                    env_vars: HashMap::new(),
+                    depends_on: vec![],
+                    platform: None,
+                    user: None,
+                    exposed_ports: vec![],
+                    entrypoint: None,
+                },
+                models::StageAnalysis {
+                    index: 2,
+                    name: Some("config-builder".to_string()),
+                    base_image: "alpine:3.18".to_string(),
+                    args: HashMap::new(),
+                    labels: HashMap::new(),
+                    env_vars: HashMap::new(),
+                    depends_on: vec![],
+                    platform: None,
+                    user: None,
+                    exposed_ports: vec![],
+                    entrypoint: None,
+                },
+                models::StageAnalysis {
+                    index: 3,
+                    name: Some("production".to_string()),
+                    base_image: "node:20-alpine".to_string(),
+                    args: HashMap::new(),
+                    labels: HashMap::new(),
+                    env_vars: HashMap::new(),
+                    depends_on: vec![0, 1, 2],
+                    platform: None,
+                    user: Some("nextjs".to_string()),
+                    exposed_ports: vec!["8080".to_string()],
+                    entrypoint: None,
+                },
+            ],
+            parsed_ports: vec![
+                models::ExposedPort {
+                    port: 8080,
+                    protocol: "tcp".to_string(),
+                },
+            ],
+            flags_used: HashMap::from([
+                ("from".to_string(), 6),
+                ("interval".to_string(), 1),
+                ("retries".to_string(), 1),
+                ("start-period".to_string(), 1),
+                ("timeout".to_string(), 1),
+            ]),
+            file_metrics: models::FileMetrics {
+                total_lines: 65,
+                comment_lines: 14,
+                blank_lines: 13,
+                instruction_lines: 38,
+            },
+            per_stage_distinct_instruction_kinds: vec![4, 4, 3, 9],
+            ordered_instructions: vec![
+                models::InstructionEntry {
+                    keyword: "FROM".to_string(),
+                    arguments: "node:20-alpine AS dependencies".to_string(),
+                    stage_index: 0,
+                    line: 3,
+                },
+                models::InstructionEntry {
+                    keyword: "WORKDIR".to_string(),
+                    arguments: "/app".to_string(),
+                    stage_index: 0,
+                    line: 4,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "package*.json ./".to_string(),
+                    stage_index: 0,
+                    line: 5,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "npm ci --only=production && \\\n    npm cache clean --force".to_string(),
+                    stage_index: 0,
+                    line: 6,
+                },
+                models::InstructionEntry {
+                    keyword: "FROM".to_string(),
+                    arguments: "node:20-alpine AS builder".to_string(),
+                    stage_index: 1,
+                    line: 10,
+                },
+                models::InstructionEntry {
+                    keyword: "WORKDIR".to_string(),
+                    arguments: "/app".to_string(),
+                    stage_index: 1,
+                    line: 11,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "package*.json ./".to_string(),
+                    stage_index: 1,
+                    line: 12,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "npm ci".to_string(),
+                    stage_index: 1,
+                    line: 13,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "src/ ./src/".to_string(),
+                    stage_index: 1,
+                    line: 14,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "public/ ./public/".to_string(),
+                    stage_index: 1,
+                    line: 15,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "tsconfig.json ./".to_string(),
+                    stage_index: 1,
+                    line: 16,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "npm run build".to_string(),
+                    stage_index: 1,
+                    line: 17,
+                },
+                models::InstructionEntry {
+                    keyword: "FROM".to_string(),
+                    arguments: "alpine:3.18 AS config-builder".to_string(),
+                    stage_index: 2,
+                    line: 20,
+                },
+                models::InstructionEntry {
+                    keyword: "WORKDIR".to_string(),
+                    arguments: "/configs".to_string(),
+                    stage_index: 2,
+                    line: 21,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "echo \"server.port=8080\" > app.properties && \\\n    echo \"database.host=localhost\" >> app.properties && \\\n    echo \"Generated config\" > app.conf && \\\n    mkdir -p assets && \\\n    echo \"Asset file content\" > assets/data.txt".to_string(),
+                    stage_index: 2,
+                    line: 22,
+                },
+                models::InstructionEntry {
+                    keyword: "FROM".to_string(),
+                    arguments: "node:20-alpine AS production".to_string(),
+                    stage_index: 3,
+                    line: 29,
+                },
+                models::InstructionEntry {
+                    keyword: "WORKDIR".to_string(),
+                    arguments: "/app".to_string(),
+                    stage_index: 3,
+                    line: 30,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "addgroup -g 1001 -S nodejs && \\\n    adduser -S nextjs -u 1001".to_string(),
+                    stage_index: 3,
+                    line: 33,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "--from=dependencies /app/node_modules ./node_modules".to_string(),
+                    stage_index: 3,
+                    line: 37,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "--from=builder /app/dist ./dist".to_string(),
+                    stage_index: 3,
+                    line: 40,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "--from=builder /app/public ./public".to_string(),
+                    stage_index: 3,
+                    line: 41,
+                },
+                models::InstructionEntry {
+                    keyword: "ADD".to_string(),
+                    arguments: "--from=config-builder /configs/app.properties ./config/".to_string(),
+                    stage_index: 3,
+                    line: 45,
+                },
+                models::InstructionEntry {
+                    keyword: "ADD".to_string(),
+                    arguments: "--from=config-builder /configs/app.conf ./config/".to_string(),
+                    stage_index: 3,
+                    line: 46,
+                },
+                models::InstructionEntry {
+                    keyword: "ADD".to_string(),
+                    arguments: "--from=config-builder /configs/assets ./assets/".to_string(),
+                    stage_index: 3,
+                    line: 47,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "package*.json ./".to_string(),
+                    stage_index: 3,
+                    line: 50,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "server.js ./".to_string(),
+                    stage_index: 3,
+                    line: 51,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "chown -R nextjs:nodejs /app".to_string(),
+                    stage_index: 3,
+                    line: 54,
+                },
+                models::InstructionEntry {
+                    keyword: "USER".to_string(),
+                    arguments: "nextjs".to_string(),
+                    stage_index: 3,
+                    line: 55,
+                },
+                models::InstructionEntry {
+                    keyword: "EXPOSE".to_string(),
+                    arguments: "8080".to_string(),
+                    stage_index: 3,
+                    line: 58,
+                },
+                models::InstructionEntry {
+                    keyword: "HEALTHCHECK".to_string(),
+                    arguments: "--interval=30s --timeout=3s --start-period=5s --retries=3 \\\n    CMD curl -f http://localhost:8080/health || exit 1".to_string(),
+                    stage_index: 3,
+                    line: 61,
+                },
+                models::InstructionEntry {
+                    keyword: "CMD".to_string(),
+                    arguments: "[\"node\", \"server.js\"]".to_string(),
+                    stage_index: 3,
+                    line: 65,
+                },
+            ],
+            copy_operations: vec![
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["package*.json".to_string()],
+                    destination: "./".to_string(),
+                    stage_index: 0,
+                    line: 5,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["package*.json".to_string()],
+                    destination: "./".to_string(),
+                    stage_index: 1,
+                    line: 12,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["src/".to_string()],
+                    destination: "./src/".to_string(),
+                    stage_index: 1,
+                    line: 14,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["public/".to_string()],
+                    destination: "./public/".to_string(),
+                    stage_index: 1,
+                    line: 15,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["tsconfig.json".to_string()],
+                    destination: "./".to_string(),
+                    stage_index: 1,
+                    line: 16,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["/app/node_modules".to_string()],
+                    destination: "./node_modules".to_string(),
+                    stage_index: 3,
+                    line: 37,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: Some("dependencies".to_string()),
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["/app/dist".to_string()],
+                    destination: "./dist".to_string(),
+                    stage_index: 3,
+                    line: 40,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: Some("builder".to_string()),
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["/app/public".to_string()],
+                    destination: "./public".to_string(),
+                    stage_index: 3,
+                    line: 41,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: Some("builder".to_string()),
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "ADD".to_string(),
+                    sources: vec!["/configs/app.properties".to_string()],
+                    destination: "./config/".to_string(),
+                    stage_index: 3,
+                    line: 45,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: Some("config-builder".to_string()),
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "ADD".to_string(),
+                    sources: vec!["/configs/app.conf".to_string()],
+                    destination: "./config/".to_string(),
+                    stage_index: 3,
+                    line: 46,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: Some("config-builder".to_string()),
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "ADD".to_string(),
+                    sources: vec!["/configs/assets".to_string()],
+                    destination: "./assets/".to_string(),
+                    stage_index: 3,
+                    line: 47,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: Some("config-builder".to_string()),
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["package*.json".to_string()],
+                    destination: "./".to_string(),
+                    stage_index: 3,
+                    line: 50,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["server.js".to_string()],
+                    destination: "./".to_string(),
+                    stage_index: 3,
+                    line: 51,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+            ],
+            run_operations: vec![
+                models::RunOperation {
+                    command: "npm ci --only=production && \\\n    npm cache clean --force".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 0,
+                    line: 6,
+                },
+                models::RunOperation {
+                    command: "npm ci".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 1,
+                    line: 13,
+                },
+                models::RunOperation {
+                    command: "npm run build".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 1,
+                    line: 17,
+                },
+                models::RunOperation {
+                    command: "echo \"server.port=8080\" > app.properties && \\\n    echo \"database.host=localhost\" >> app.properties && \\\n    echo \"Generated config\" > app.conf && \\\n    mkdir -p assets && \\\n    echo \"Asset file content\" > assets/data.txt".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 2,
+                    line: 22,
+                },
+                models::RunOperation {
+                    command: "addgroup -g 1001 -S nodejs && \\\n    adduser -S nextjs -u 1001".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 3,
+                    line: 33,
+                },
+                models::RunOperation {
+                    command: "chown -R nextjs:nodejs /app".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 3,
+                    line: 54,
+                },
+            ],
+            created_users: vec!["nextjs".to_string()],
+            findings: vec![
+                models::Finding {
+                    rule_id: "prefer-copy".to_string(),
+                    severity: "warning".to_string(),
+                    message: "ADD of local files to './config/' does nothing COPY doesn't; use COPY instead".to_string(),
+                    line: Some(45),
+                },
+                models::Finding {
+                    rule_id: "prefer-copy".to_string(),
+                    severity: "warning".to_string(),
+                    message: "ADD of local files to './config/' does nothing COPY doesn't; use COPY instead".to_string(),
+                    line: Some(46),
+                },
+                models::Finding {
+                    rule_id: "prefer-copy".to_string(),
+                    severity: "warning".to_string(),
+                    message: "ADD of local files to './assets/' does nothing COPY doesn't; use COPY instead".to_string(),
+                    line: Some(47),
+                },
+            ],
+            quality_score: 70,
+            quality_deductions: HashMap::from([("prefer-copy".to_string(), 30)]),
+            ..Default::default()
+        };
+        let res = analyze_dockerfile(dockerfile);
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_multistage_with_copy_and_add2() {
+        let dockerfile = r#"
+# Stage 1: Download and prepare external dependencies
+FROM alpine:3.18 AS downloader
+RUN apk add --no-cache curl tar
+WORKDIR /downloads
+RUN curl -L https://github.com/some-project/releases/download/v1.0.0/binary.tar.gz -o binary.tar.gz && \
+    tar -xzf binary.tar.gz
+
+# Stage 2: Compile application
+FROM golang:1.21-alpine AS go-builder
+WORKDIR /src
+COPY go.mod go.sum ./
+RUN go mod download
+COPY . .
+RUN CGO_ENABLED=0 GOOS=linux go build -o app .
+
+# Stage 3: Generate certificates and configs
+FROM alpine:3.18 AS cert-generator
+RUN apk add --no-cache openssl
+WORKDIR /certs
+RUN openssl req -x509 -newkey rsa:4096 -keyout key.pem -out cert.pem -days 365 -nodes \
+    -subj "/C=US/ST=State/L=City/O=Organization/CN=localhost"
+RUN echo "tls_cert=/app/certs/cert.pem" > server.conf && \
+    echo "tls_key=/app/certs/key.pem" >> server.conf
+
+# Stage 4: Final runtime image
+FROM alpine:3.18
+RUN apk add --no-cache ca-certificates tzdata
+WORKDIR /app
+
+# Copy binary from Go builder stage
+COPY --from=go-builder /src/app ./
+
+# Copy external dependencies using ADD --from
+ADD --from=downloader /downloads/binary ./bin/
+ADD --from=downloader /downloads/config/ ./external-config/
+
+# Copy certificates and config using COPY --from
+COPY --from=cert-generator /certs/*.pem ./certs/
+COPY --from=cert-generator /certs/server.conf ./config/
+
+# Create necessary directories and set permissions
+RUN mkdir -p /app/logs && \
+    adduser -D -s /bin/sh appuser && \
+    chown -R appuser:appuser /app
+
+USER appuser
+EXPOSE 8080 8443
+
+CMD ["./app"]
+"#;
+
+        let msa = models::MultistageAnalysis {
+            is_multistage: true,
+            has_multiple_stages: true,
+            stages_used_as_base_images: vec![],
+            stages_copied_from: vec!["cert-generator".to_string(), "go-builder".to_string()],
+            stages_added_from: vec!["downloader".to_string()],
+            unused_stages: vec![],
+            artifact_only_stages: vec![
+                "cert-generator".to_string(),
+                "downloader".to_string(),
+                "go-builder".to_string(),
+            ],
+        };
+        let images: Vec<models::Image> = vec![
+            models::Image {
+                full: "alpine:3.18".to_string(),
+                components: Some(models::ImageComponents {
+                    registry: None,
+                    name: "alpine".to_string(),
+                    tag: Some("3.18".to_string()),
+                    digest: None,
+                }),
+            },
+            models::Image {
+                full: "golang:1.21-alpine".to_string(),
+                components: Some(models::ImageComponents {
+                    registry: None,
+                    name: "golang".to_string(),
+                    tag: Some("1.21-alpine".to_string()),
+                    digest: None,
+                }),
+            },
+        ];
+        let instructions = models::InstructionStats {
+            total_count: 27,
             by_type: HashMap::from([
-                ("ARG".to_string(), 1),
+                ("ADD".to_string(), 2),
                 ("CMD".to_string(), 1),
                 ("COPY".to_string(), 5),
-                ("ENV".to_string(), 2),
                 ("EXPOSE".to_string(), 1),
-                ("FROM".to_string(), 3),
-                ("LABEL".to_string(), 1),
-                ("RUN".to_string(), 4),
-                ("USER".to_string(), 3),
-                ("WORKDIR".to_string(), 1),
+                ("FROM".to_string(), 4),
+                ("RUN".to_string(), 9),
+                ("USER".to_string(), 1),
+                ("WORKDIR".to_string(), 4),
             ]),
+            by_type_per_stage: None,
         };
 
-        let env_vars = HashMap::from([
-            ("PYTHONPATH".into(), "/src".into()),
-            ("PYTHONUNBUFFERED".into(), "1".into()),
-            (
-                "REQUESTS_CA_BUNDLE".into(),
-                "/etc/ssl/certs/ca-certificates.crt".into(),
-            ),
-            ("PATH".into(), "/home/appuser/.local/bin:$PATH".into()),
-            ("GIT_COMMIT".into(), "$GIT_COMMIT".into()),
-        ]);
-
-        let args = HashMap::from([("GIT_COMMIT".into(), None)]);
-        let labels = HashMap::from([
-            ("org.opencontainers.image.title".into(), "My App".into()),
-            ("org.opencontainers.image.version".into(), "1.0".into()),
-            (
-                "org.opencontainers.image.authors".into(),
-                "john@example.com".into(),
-            ),
-        ]);
-
         let expected = models::Analysis {
-            num_stages: 3,
-            stage_names: vec!["base".to_string(), "test".to_string()],
+            num_stages: 4,
+            stage_names: vec![
+                "cert-generator".to_string(),
+                "downloader".to_string(),
+                "go-builder".to_string(),
+            ],
             images,
-            copy_from_stages: vec![],
-            add_from_stages: vec![],
+            copy_from_stages: vec!["cert-generator".to_string(), "go-builder".to_string()],
+            add_from_stages: vec!["downloader".to_string()],
             multistage_analysis: msa,
-            exposed_ports: vec!["5000".to_string()],
+            exposed_ports: vec!["8080".to_string(), "8443".to_string()],
             instructions,
-            args,
-            labels,
-            env_vars,
+            args: HashMap::new(),
+            labels: HashMap::new(),
+            env_vars: HashMap::new(),
+            escape_char: '\\',
+            final_base_chain: vec!["alpine:3.18".to_string()],
+            distinct_base_images: 2,
+            reused_base_images: vec!["alpine:3.18".to_string()],
+            registries: vec!["docker.io".to_string()],
+            stages: vec![
+                models::StageAnalysis {
+                    index: 0,
+                    name: Some("downloader".to_string()),
+                    base_image: "alpine:3.18".to_string(),
+                    args: HashMap::new(),
+                    labels: HashMap::new(),
+                    env_vars: HashMap::new(),
+                    depends_on: vec![],
+                    platform: None,
+                    user: None,
+                    exposed_ports: vec![],
+                    entrypoint: None,
+                },
+                models::StageAnalysis {
+                    index: 1,
+                    name: Some("go-builder".to_string()),
+                    base_image: "golang:1.21-alpine".to_string(),
+                    args: HashMap::new(),
+                    labels: HashMap::new(),
+                    env_vars: HashMap::new(),
+                    depends_on: vec![],
+                    platform: None,
+                    user: None,
+                    exposed_ports: vec![],
+                    entrypoint: None,
+                },
+                models::StageAnalysis {
+                    index: 2,
+                    name: Some("cert-generator".to_string()),
+                    base_image: "alpine:3.18".to_string(),
+                    args: HashMap::new(),
+                    labels: HashMap::new(),
+                    env_vars: HashMap::new(),
+                    depends_on: vec![],
+                    platform: None,
+                    user: None,
+                    exposed_ports: vec![],
+                    entrypoint: None,
+                },
+                models::StageAnalysis {
+                    index: 3,
+                    name: None,
+                    base_image: "alpine:3.18".to_string(),
+                    args: HashMap::new(),
+                    labels: HashMap::new(),
+                    env_vars: HashMap::new(),
+                    depends_on: vec![0, 1, 2],
+                    platform: None,
+                    user: Some("appuser".to_string()),
+                    exposed_ports: vec!["8080".to_string(), "8443".to_string()],
+                    entrypoint: None,
+                },
+            ],
+            parsed_ports: vec![
+                models::ExposedPort {
+                    port: 8080,
+                    protocol: "tcp".to_string(),
+                },
+                models::ExposedPort {
+                    port: 8443,
+                    protocol: "tcp".to_string(),
+                },
+            ],
+            flags_used: HashMap::from([("from".to_string(), 5)]),
+            file_metrics: models::FileMetrics {
+                total_lines: 50,
+                comment_lines: 8,
+                blank_lines: 10,
+                instruction_lines: 32,
+            },
+            per_stage_distinct_instruction_kinds: vec![3, 4, 3, 8],
+            ordered_instructions: vec![
+                models::InstructionEntry {
+                    keyword: "FROM".to_string(),
+                    arguments: "alpine:3.18 AS downloader".to_string(),
+                    stage_index: 0,
+                    line: 3,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "apk add --no-cache curl tar".to_string(),
+                    stage_index: 0,
+                    line: 4,
+                },
+                models::InstructionEntry {
+                    keyword: "WORKDIR".to_string(),
+                    arguments: "/downloads".to_string(),
+                    stage_index: 0,
+                    line: 5,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "curl -L https://github.com/some-project/releases/download/v1.0.0/binary.tar.gz -o binary.tar.gz && \\\n    tar -xzf binary.tar.gz".to_string(),
+                    stage_index: 0,
+                    line: 6,
+                },
+                models::InstructionEntry {
+                    keyword: "FROM".to_string(),
+                    arguments: "golang:1.21-alpine AS go-builder".to_string(),
+                    stage_index: 1,
+                    line: 10,
+                },
+                models::InstructionEntry {
+                    keyword: "WORKDIR".to_string(),
+                    arguments: "/src".to_string(),
+                    stage_index: 1,
+                    line: 11,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "go.mod go.sum ./".to_string(),
+                    stage_index: 1,
+                    line: 12,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "go mod download".to_string(),
+                    stage_index: 1,
+                    line: 13,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: ". .".to_string(),
+                    stage_index: 1,
+                    line: 14,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "CGO_ENABLED=0 GOOS=linux go build -o app .".to_string(),
+                    stage_index: 1,
+                    line: 15,
+                },
+                models::InstructionEntry {
+                    keyword: "FROM".to_string(),
+                    arguments: "alpine:3.18 AS cert-generator".to_string(),
+                    stage_index: 2,
+                    line: 18,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "apk add --no-cache openssl".to_string(),
+                    stage_index: 2,
+                    line: 19,
+                },
+                models::InstructionEntry {
+                    keyword: "WORKDIR".to_string(),
+                    arguments: "/certs".to_string(),
+                    stage_index: 2,
+                    line: 20,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "openssl req -x509 -newkey rsa:4096 -keyout key.pem -out cert.pem -days 365 -nodes \\\n    -subj \"/C=US/ST=State/L=City/O=Organization/CN=localhost\"".to_string(),
+                    stage_index: 2,
+                    line: 21,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "echo \"tls_cert=/app/certs/cert.pem\" > server.conf && \\\n    echo \"tls_key=/app/certs/key.pem\" >> server.conf".to_string(),
+                    stage_index: 2,
+                    line: 23,
+                },
+                models::InstructionEntry {
+                    keyword: "FROM".to_string(),
+                    arguments: "alpine:3.18".to_string(),
+                    stage_index: 3,
+                    line: 27,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "apk add --no-cache ca-certificates tzdata".to_string(),
+                    stage_index: 3,
+                    line: 28,
+                },
+                models::InstructionEntry {
+                    keyword: "WORKDIR".to_string(),
+                    arguments: "/app".to_string(),
+                    stage_index: 3,
+                    line: 29,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "--from=go-builder /src/app ./".to_string(),
+                    stage_index: 3,
+                    line: 32,
+                },
+                models::InstructionEntry {
+                    keyword: "ADD".to_string(),
+                    arguments: "--from=downloader /downloads/binary ./bin/".to_string(),
+                    stage_index: 3,
+                    line: 35,
+                },
+                models::InstructionEntry {
+                    keyword: "ADD".to_string(),
+                    arguments: "--from=downloader /downloads/config/ ./external-config/".to_string(),
+                    stage_index: 3,
+                    line: 36,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "--from=cert-generator /certs/*.pem ./certs/".to_string(),
+                    stage_index: 3,
+                    line: 39,
+                },
+                models::InstructionEntry {
+                    keyword: "COPY".to_string(),
+                    arguments: "--from=cert-generator /certs/server.conf ./config/".to_string(),
+                    stage_index: 3,
+                    line: 40,
+                },
+                models::InstructionEntry {
+                    keyword: "RUN".to_string(),
+                    arguments: "mkdir -p /app/logs && \\\n    adduser -D -s /bin/sh appuser && \\\n    chown -R appuser:appuser /app".to_string(),
+                    stage_index: 3,
+                    line: 43,
+                },
+                models::InstructionEntry {
+                    keyword: "USER".to_string(),
+                    arguments: "appuser".to_string(),
+                    stage_index: 3,
+                    line: 47,
+                },
+                models::InstructionEntry {
+                    keyword: "EXPOSE".to_string(),
+                    arguments: "8080 8443".to_string(),
+                    stage_index: 3,
+                    line: 48,
+                },
+                models::InstructionEntry {
+                    keyword: "CMD".to_string(),
+                    arguments: "[\"./app\"]".to_string(),
+                    stage_index: 3,
+                    line: 50,
+                },
+            ],
+            copy_operations: vec![
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["go.mod".to_string(), "go.sum".to_string()],
+                    destination: "./".to_string(),
+                    stage_index: 1,
+                    line: 12,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec![".".to_string()],
+                    destination: ".".to_string(),
+                    stage_index: 1,
+                    line: 14,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["/src/app".to_string()],
+                    destination: "./".to_string(),
+                    stage_index: 3,
+                    line: 32,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: Some("go-builder".to_string()),
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "ADD".to_string(),
+                    sources: vec!["/downloads/binary".to_string()],
+                    destination: "./bin/".to_string(),
+                    stage_index: 3,
+                    line: 35,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: Some("downloader".to_string()),
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "ADD".to_string(),
+                    sources: vec!["/downloads/config/".to_string()],
+                    destination: "./external-config/".to_string(),
+                    stage_index: 3,
+                    line: 36,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: Some("downloader".to_string()),
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["/certs/*.pem".to_string()],
+                    destination: "./certs/".to_string(),
+                    stage_index: 3,
+                    line: 39,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: Some("cert-generator".to_string()),
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["/certs/server.conf".to_string()],
+                    destination: "./config/".to_string(),
+                    stage_index: 3,
+                    line: 40,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: Some("cert-generator".to_string()),
+                    checksum: None,
+                    link: false,
+                },
+            ],
+            run_operations: vec![
+                models::RunOperation {
+                    command: "apk add --no-cache curl tar".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 0,
+                    line: 4,
+                },
+                models::RunOperation {
+                    command: "curl -L https://github.com/some-project/releases/download/v1.0.0/binary.tar.gz -o binary.tar.gz && \\\n    tar -xzf binary.tar.gz".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 0,
+                    line: 6,
+                },
+                models::RunOperation {
+                    command: "go mod download".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 1,
+                    line: 13,
+                },
+                models::RunOperation {
+                    command: "CGO_ENABLED=0 GOOS=linux go build -o app .".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 1,
+                    line: 15,
+                },
+                models::RunOperation {
+                    command: "apk add --no-cache openssl".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 2,
+                    line: 19,
+                },
+                models::RunOperation {
+                    command: "openssl req -x509 -newkey rsa:4096 -keyout key.pem -out cert.pem -days 365 -nodes \\\n    -subj \"/C=US/ST=State/L=City/O=Organization/CN=localhost\"".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 2,
+                    line: 21,
+                },
+                models::RunOperation {
+                    command: "echo \"tls_cert=/app/certs/cert.pem\" > server.conf && \\\n    echo \"tls_key=/app/certs/key.pem\" >> server.conf".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 2,
+                    line: 23,
+                },
+                models::RunOperation {
+                    command: "apk add --no-cache ca-certificates tzdata".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 3,
+                    line: 28,
+                },
+                models::RunOperation {
+                    command: "mkdir -p /app/logs && \\\n    adduser -D -s /bin/sh appuser && \\\n    chown -R appuser:appuser /app".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 3,
+                    line: 43,
+                },
+            ],
+            created_users: vec!["appuser".to_string()],
+            findings: vec![
+                models::Finding {
+                    rule_id: "prefer-copy".to_string(),
+                    severity: "warning".to_string(),
+                    message: "ADD of local files to './bin/' does nothing COPY doesn't; use COPY instead".to_string(),
+                    line: Some(35),
+                },
+                models::Finding {
+                    rule_id: "prefer-copy".to_string(),
+                    severity: "warning".to_string(),
+                    message: "ADD of local files to './external-config/' does nothing COPY doesn't; use COPY instead".to_string(),
+                    line: Some(36),
+                },
+            ],
+            quality_score: 80,
+            quality_deductions: HashMap::from([("prefer-copy".to_string(), 20)]),
+            ..Default::default()
+        };
+        let res = analyze_dockerfile(dockerfile);
+        assert!(res.is_ok());
+        let analysis = res.unwrap();
+        assert_eq!(analysis, expected);
+        assert!(
+            analysis
+                .findings
+                .iter()
+                .all(|f| f.rule_id != "apk-add-without-no-cache")
+        );
+    }
+
+    #[test]
+    fn test_copy_operations() {
+        let dockerfile = r#"
+FROM alpine:3.18 AS assets
+COPY ["a b.txt", "c.txt", "/data/"]
+
+FROM ubuntu:20.04
+ADD --from=assets /data/ ./assets/
+COPY <<EOF /etc/motd
+hello
+EOF
+"#;
+        let res = analyze_dockerfile(dockerfile);
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap().copy_operations,
+            vec![
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["a b.txt".to_string(), "c.txt".to_string()],
+                    destination: "/data/".to_string(),
+                    stage_index: 0,
+                    line: 3,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "ADD".to_string(),
+                    sources: vec!["/data/".to_string()],
+                    destination: "./assets/".to_string(),
+                    stage_index: 1,
+                    line: 6,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: Some("assets".to_string()),
+                    checksum: None,
+                    link: false,
+                },
+                models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["<<heredoc>>".to_string()],
+                    destination: "/etc/motd".to_string(),
+                    stage_index: 1,
+                    line: 7,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_copy_link() {
+        let dockerfile = "FROM alpine\nCOPY --link app /app\nCOPY --link=false conf /etc/app/\nCOPY run.sh /bin/\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        let links: Vec<bool> = analysis.copy_operations.iter().map(|op| op.link).collect();
+        assert_eq!(links, vec![true, false, false]);
+        assert!(analysis.uses_copy_link);
+    }
+
+    #[test]
+    fn test_plain_copy_does_not_use_link() {
+        let analysis = analyze_dockerfile("FROM alpine\nCOPY app /app\n").unwrap();
+        assert!(!analysis.copy_operations[0].link);
+        assert!(!analysis.uses_copy_link);
+    }
+
+    #[test]
+    fn test_copy_operations_chmod() {
+        let dockerfile = "FROM alpine\nADD --chmod=a+rwx run.sh /usr/local/bin/\nCOPY --chmod=0644 app.conf /etc/app/\nCOPY --chmod=u+x tool /bin/\n";
+        let ops = analyze_dockerfile(dockerfile).unwrap().copy_operations;
+        let chmods: Vec<_> = ops
+            .iter()
+            .map(|op| (op.chmod.as_deref(), op.chmod_octal))
+            .collect();
+        assert_eq!(
+            chmods,
+            vec![
+                (Some("a+rwx"), Some(0o777)),
+                (Some("0644"), Some(0o644)),
+                (Some("u+x"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_operations_network() {
+        let dockerfile = "FROM golang:1.22\nRUN go mod download\nRUN --network=none make\nRUN [\"go\", \"build\", \"./...\"]\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(
+            analysis.run_operations,
+            vec![
+                models::RunOperation {
+                    command: "go mod download".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 0,
+                    line: 2,
+                },
+                models::RunOperation {
+                    command: "make".to_string(),
+                    network: Some("none".to_string()),
+                    shell: None,
+                    stage_index: 0,
+                    line: 3,
+                },
+                models::RunOperation {
+                    command: "go build ./...".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 0,
+                    line: 4,
+                },
+            ]
+        );
+        assert!(analysis.has_offline_runs);
+    }
+
+    #[test]
+    fn test_run_operations_shell() {
+        let dockerfile = r#"
+FROM mcr.microsoft.com/windows/servercore:ltsc2022 AS build
+SHELL ["powershell", "-Command"]
+RUN Write-Host hello
+
+FROM alpine
+RUN echo hello
+"#;
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(
+            analysis.run_operations[0].shell,
+            Some(vec!["powershell".to_string(), "-Command".to_string()])
+        );
+        assert_eq!(analysis.run_operations[1].shell, None);
+    }
+
+    #[test]
+    fn test_default_run_is_not_offline() {
+        let analysis =
+            analyze_dockerfile("FROM alpine\nRUN --network=host apk add curl\nRUN echo hi\n")
+                .unwrap();
+        assert_eq!(analysis.run_operations[0].network.as_deref(), Some("host"));
+        assert_eq!(analysis.run_operations[1].network, None);
+        assert!(!analysis.has_offline_runs);
+    }
+
+    #[test]
+    fn test_exclude_stage_references_from_images() {
+        let options = models::AnalysisOptions {
+            exclude_stage_references_from_images: true,
+            ..Default::default()
         };
+        let res = analyze_dockerfile_with_options(MULTISTAGE_DOCKERFILE, &options);
+        assert!(res.is_ok());
+        let analysis = res.unwrap();
+        let images: Vec<&str> = analysis.images.iter().map(|i| i.full.as_str()).collect();
+        assert_eq!(
+            images,
+            vec![
+                "docker.abc.com/base-images/python:3.13-debian@sha256:55f1d15ef4c37870e23c03e89ad238940b55c8ede9f13fac4b7d71c7955f1053"
+            ]
+        );
+        assert_eq!(
+            analysis.multistage_analysis.stages_used_as_base_images,
+            vec!["base".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_base_image_reuse() {
+        let analysis = analyze_dockerfile(MULTISTAGE_COPY_AND_ADD_DOCKERFILE).unwrap();
+        assert_eq!(analysis.distinct_base_images, 2);
+        assert_eq!(analysis.reused_base_images, vec!["node:20-alpine"]);
+
+        let analysis = analyze_dockerfile(MULTISTAGE_DOCKERFILE).unwrap();
+        assert!(analysis.reused_base_images.is_empty());
+        assert_eq!(analysis.distinct_base_images, 1);
+    }
+
+    #[test]
+    fn test_digest_pinned_images() {
+        const DIGEST: &str =
+            "sha256:55f1d15ef4c37870e23c03e89ad238940b55c8ede9f13fac4b7d71c7955f1053";
+        let dockerfile = format!(
+            "FROM python:3.13 AS tagged\nFROM python@{DIGEST} AS digest\nFROM python:3.13-slim@{DIGEST}\n"
+        );
+        let analysis = analyze_dockerfile(&dockerfile).unwrap();
+        let pinned: Vec<(&str, bool)> = analysis
+            .images
+            .iter()
+            .map(|image| (image.full.as_str(), image.is_digest_pinned()))
+            .collect();
+        assert_eq!(
+            pinned,
+            vec![
+                ("python:3.13", false),
+                (format!("python:3.13-slim@{DIGEST}").as_str(), true),
+                (format!("python@{DIGEST}").as_str(), true),
+            ]
+        );
+        assert_eq!(
+            analysis.digest_pinned_images,
+            vec![
+                format!("python:3.13-slim@{DIGEST}"),
+                format!("python@{DIGEST}")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_oci_label_report() {
+        let analysis = analyze_dockerfile(MULTISTAGE_DOCKERFILE).unwrap();
+        let report = analysis.oci_label_report();
+        assert_eq!(
+            report.present,
+            vec![
+                "org.opencontainers.image.title",
+                "org.opencontainers.image.version",
+                "org.opencontainers.image.authors",
+            ]
+        );
+        assert!(
+            report
+                .missing
+                .contains(&"org.opencontainers.image.source".to_string())
+        );
+        assert!(
+            report
+                .missing
+                .contains(&"org.opencontainers.image.revision".to_string())
+        );
+        assert_eq!(
+            report.present.len() + report.missing.len(),
+            constants::OCI_RECOMMENDED_LABELS.len()
+        );
+    }
+
+    #[test]
+    fn test_analyze_dockerfiles_parallel_matches_sequential() {
+        let bodies: Vec<String> = [
+            MULTISTAGE_DOCKERFILE,
+            SINGLE_STAGE_DOCKERFILE,
+            MULTISTAGE_COPY_AND_ADD_DOCKERFILE,
+            "COPY . .",
+            "FROM alpine:3.18\nEXPOSE 80\n",
+        ]
+        .iter()
+        .cycle()
+        .take(50)
+        .map(|body| body.to_string())
+        .collect();
+        let options = models::AnalysisOptions::default();
+
+        let sequential = analyze_dockerfiles(&bodies, &options, false);
+        let parallel = analyze_dockerfiles(&bodies, &options, true);
+        assert_eq!(parallel, sequential);
+        assert_eq!(parallel.len(), bodies.len());
+        assert!(parallel[3].is_err());
+        assert_eq!(
+            parallel[0].as_ref().unwrap(),
+            &analyze_dockerfile(MULTISTAGE_DOCKERFILE).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_instruction_keywords() {
+        let body = "FROM alpine AS build\n# comment\nRUN apk add \\\n    curl\nCOPY . /src\n\nFROM scratch\nCOPY --from=build /src /\n";
+        let keywords: Result<Vec<_>, _> = super::instruction_keywords(body).unwrap().collect();
+        assert_eq!(
+            keywords.unwrap(),
+            vec![
+                ("FROM", 1),
+                ("RUN", 3),
+                ("COPY", 5),
+                ("FROM", 7),
+                ("COPY", 8)
+            ]
+        );
+        assert!(super::instruction_keywords("").is_err());
+
+        let mut keywords =
+            super::instruction_keywords("FROM alpine\nRUN true\nFOO bar\nRUN false\n").unwrap();
+        assert_eq!(keywords.next(), Some(Ok(("FROM", 1))));
+        assert_eq!(keywords.next(), Some(Ok(("RUN", 2))));
+        assert!(matches!(
+            keywords.next(),
+            Some(Err(AnalyzeError::ParseError(_)))
+        ));
+        assert_eq!(keywords.next(), None);
+
+        let mut keywords = super::instruction_keywords("RUN true\nFROM alpine\n").unwrap();
+        assert!(matches!(
+            keywords.next(),
+            Some(Err(AnalyzeError::InstructionBeforeFrom(_)))
+        ));
+    }
+
+    #[test]
+    fn test_analyze_multistage_only_matches_full_analysis() {
+        for dockerfile in [MULTISTAGE_DOCKERFILE, SINGLE_STAGE_DOCKERFILE] {
+            let full = analyze_dockerfile(dockerfile).unwrap();
+            let multistage = analyze_multistage_only(dockerfile).unwrap();
+            assert_eq!(multistage, full.multistage_analysis);
+        }
+        assert!(analyze_multistage_only("COPY . .").is_err());
+    }
+
+    #[test]
+    fn test_per_stage_distinct_instruction_kinds() {
+        let analysis = analyze_dockerfile(MULTISTAGE_DOCKERFILE).unwrap();
+        assert_eq!(analysis.per_stage_distinct_instruction_kinds, vec![7, 4, 7]);
+
+        let single = analyze_dockerfile(SINGLE_STAGE_DOCKERFILE).unwrap();
+        assert_eq!(single.per_stage_distinct_instruction_kinds.len(), 1);
+    }
+
+    #[test]
+    fn test_parsed_ports_expand_ranges() {
+        let dockerfile = "FROM alpine\nEXPOSE 80 3000-3002/udp $PORT\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        let ports: Vec<(u16, &str)> = analysis
+            .parsed_ports
+            .iter()
+            .map(|p| (p.port, p.protocol.as_str()))
+            .collect();
+        assert_eq!(
+            ports,
+            vec![(80, "tcp"), (3000, "udp"), (3001, "udp"), (3002, "udp")]
+        );
+        assert_eq!(analysis.exposed_ports, vec!["$PORT", "3000-3002/udp", "80"]);
+    }
+
+    #[test]
+    fn test_parsed_ports_skip_reversed_and_large_ranges() {
+        let dockerfile = "FROM alpine\nEXPOSE 3005-3000 1000-9000 8080\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(
+            analysis.parsed_ports,
+            vec![models::ExposedPort {
+                port: 8080,
+                protocol: "tcp".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_global_and_stage_args() {
+        let dockerfile = r#"
+ARG BASE_IMAGE=python:3.13-slim
+FROM $BASE_IMAGE AS base
+ARG GIT_COMMIT
+ENV GIT_COMMIT=$GIT_COMMIT
 
+FROM base
+ARG BUILD_DATE=unknown
+"#;
         let res = analyze_dockerfile(dockerfile);
         assert!(res.is_ok());
         let analysis = res.unwrap();
-        assert_eq!(analysis, expected);
+        assert_eq!(
+            analysis.global_args,
+            HashMap::from([("BASE_IMAGE".into(), Some("python:3.13-slim".into()))])
+        );
+        assert_eq!(
+            analysis.stages,
+            vec![
+                models::StageAnalysis {
+                    index: 0,
+                    name: Some("base".to_string()),
+                    base_image: "$BASE_IMAGE".to_string(),
+                    args: HashMap::from([("GIT_COMMIT".into(), None)]),
+                    labels: HashMap::new(),
+                    env_vars: HashMap::from([("GIT_COMMIT".into(), "$GIT_COMMIT".into())]),
+                    depends_on: vec![],
+                    platform: None,
+                    user: None,
+                    exposed_ports: vec![],
+                    entrypoint: None,
+                },
+                models::StageAnalysis {
+                    index: 1,
+                    name: None,
+                    base_image: "base".to_string(),
+                    args: HashMap::from([("BUILD_DATE".into(), Some("unknown".into()))]),
+                    labels: HashMap::new(),
+                    env_vars: HashMap::new(),
+                    depends_on: vec![0],
+                    platform: None,
+                    user: None,
+                    exposed_ports: vec![],
+                    entrypoint: None,
+                },
+            ]
+        );
+        assert_eq!(analysis.args.len(), 3);
     }
 
     #[test]
-    fn test_invalid_dockerfile() {
-        let res = analyze_dockerfile("invalid dockerfile content");
-        assert!(res.is_err());
-        let err_text = res.unwrap_err().to_string();
-        assert!(err_text.contains("unknown instruction 'invalid'"));
+    fn test_runtime_instructions_by_stage() {
+        let analysis = analyze_dockerfile(MULTISTAGE_DOCKERFILE).unwrap();
+        let users: Vec<Option<&str>> = analysis
+            .stages
+            .iter()
+            .map(|stage| stage.user.as_deref())
+            .collect();
+        assert_eq!(
+            users,
+            vec![Some("root:root"), Some("1000:1000"), Some("1000:1000")]
+        );
+        let ports: Vec<&[String]> = analysis
+            .stages
+            .iter()
+            .map(|stage| stage.exposed_ports.as_slice())
+            .collect();
+        assert_eq!(ports, vec![&[][..], &[][..], &["5000".to_string()][..]]);
+        assert!(
+            analysis
+                .stages
+                .iter()
+                .all(|stage| stage.entrypoint.is_none())
+        );
+
+        let dockerfile = "FROM alpine\nENTRYPOINT [\"/docker-entrypoint.sh\"]\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(
+            analysis.stages[0].entrypoint.as_deref(),
+            Some("[\"/docker-entrypoint.sh\"]")
+        );
     }
-    #[test]
-    fn test_single_stage() {
-        let dockerfile = r#"
-FROM node:20-alpine
 
-# Set working directory
-WORKDIR /app
+    #[test]
+    fn test_arg_usages() {
+        let res = analyze_dockerfile(MULTISTAGE_DOCKERFILE);
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap().arg_usages,
+            HashMap::from([("GIT_COMMIT".into(), vec!["ENV".to_string()])])
+        );
+    }
 
-# Copy package files
-COPY package*.json ./
+    #[test]
+    fn test_arg_usages_in_from_and_unused() {
+        let dockerfile = r#"
+ARG BASE_IMAGE=node:18-alpine
+ARG UNUSED
+FROM ${BASE_IMAGE} AS builder
+RUN echo $BASE_IMAGE
+"#;
+        let res = analyze_dockerfile(dockerfile);
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap().arg_usages,
+            HashMap::from([
+                (
+                    "BASE_IMAGE".into(),
+                    vec!["FROM".to_string(), "RUN".to_string()]
+                ),
+                ("UNUSED".into(), vec![]),
+            ])
+        );
+    }
 
-# Install dependencies
-RUN npm install
+    #[test]
+    fn test_required_build_args() {
+        let res = analyze_dockerfile(MULTISTAGE_DOCKERFILE);
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap().required_build_args, vec!["GIT_COMMIT"]);
 
-# Copy application source code
-COPY . .
+        let dockerfile = r#"
+ARG BASE_IMAGE=node:18-alpine
+ARG UNUSED
+FROM ${BASE_IMAGE}
+ARG VERSION
+RUN echo $BASE_IMAGE $VERSION
+"#;
+        let res = analyze_dockerfile(dockerfile);
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap().required_build_args, vec!["VERSION"]);
 
-# Create non-root user
-RUN addgroup -g 1001 -S nodejs && \
-    adduser -S nextjs -u 1001
+        let dockerfile =
+            "ARG VERSION=3.18\nFROM alpine:${VERSION}\nARG VERSION\nRUN echo $VERSION\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert!(analysis.required_build_args.is_empty());
 
-# Change ownership of the app directory
-RUN chown -R nextjs:nodejs /app
+        let dockerfile =
+            "FROM alpine\nARG VERSION=3.18\nFROM alpine\nARG VERSION\nRUN echo $VERSION\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(analysis.required_build_args, vec!["VERSION"]);
+    }
 
-# Switch to non-root user
-USER nextjs
+    #[test]
+    fn test_port_numbers() {
+        let dockerfile = "FROM nginx:1.25\nEXPOSE 8443 8080\nEXPOSE 8080/udp\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(analysis.port_numbers(), vec![8080, 8443]);
+    }
 
-# Expose port
-EXPOSE 3000
+    #[test]
+    fn test_exposed_protocols() {
+        let dockerfile = "FROM coredns/coredns\nEXPOSE 53/udp 53/tcp 9153\nEXPOSE 8181/UDP\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(analysis.exposed_protocols(), vec!["tcp", "udp"]);
 
-# Set environment variable
-ENV NODE_ENV=production
+        let analysis = analyze_dockerfile("FROM alpine\n").unwrap();
+        assert!(analysis.exposed_protocols().is_empty());
+    }
 
-# Start the application
-CMD ["npm", "start"]
+    #[test]
+    fn test_quality_score() {
+        let clean = r#"
+FROM python:3.13-slim
+WORKDIR /app
+COPY requirements.txt ./
+RUN pip install --no-cache-dir -r requirements.txt
+COPY . .
+RUN useradd --create-home app
+USER app
+CMD ["python", "app.py"]
 "#;
-        let msa = models::MultistageAnalysis {
-            is_multistage: false,
-            stages_used_as_base_images: vec![],
-            stages_copied_from: vec![],
-            stages_added_from: vec![],
-            unused_stages: vec![],
-        };
-        let images: Vec<models::Image> = vec![models::Image {
-            full: "node:20-alpine".to_string(),
-            components: Some(models::ImageComponents {
-                registry: None,
-                name: "node".to_string(),
-                tag: Some("20-alpine".to_string()),
-                digest: None,
-            }),
-        }];
+        let analysis = analyze_dockerfile(clean).unwrap();
+        assert_eq!(analysis.quality_score, 100);
+        assert!(analysis.quality_deductions.is_empty());
+
+        let poor = r#"
+FROM http://registry.example.com/python:3.13
+RUN apt-get update && apt-get upgrade -y
+EXPOSE 70000
+CMD python app.py
+"#;
+        let analysis = analyze_dockerfile(poor).unwrap();
+        assert_eq!(
+            analysis.quality_deductions,
+            HashMap::from([
+                ("insecure-registry".into(), 10),
+                ("package-upgrade".into(), 10),
+                ("invalid-expose-port".into(), 20),
+                ("prefer-exec-form".into(), 10),
+            ])
+        );
+        assert_eq!(analysis.quality_score, 50);
+
+        let bloated = r#"
+FROM python:latest
+WORKDIR /app
+RUN apt-get update && apt-get install -y --no-install-recommends curl
+USER root
+CMD ["python", "app.py"]
+"#;
+        let analysis = analyze_dockerfile(bloated).unwrap();
+        assert_eq!(
+            analysis.quality_deductions,
+            HashMap::from([
+                ("floating-tag".into(), 10),
+                ("apt-lists-not-cleaned".into(), 2),
+                ("root-user".into(), 10),
+            ])
+        );
+        assert_eq!(analysis.quality_score, 78);
+    }
 
-        let instructions = models::InstructionStats {
-            total_count: 11,
-            by_type: HashMap::from([
-                ("CMD".to_string(), 1),
-                ("COPY".to_string(), 2),
-                ("ENV".to_string(), 1),
-                ("EXPOSE".to_string(), 1),
-                ("FROM".to_string(), 1),
-                ("RUN".to_string(), 3),
-                ("USER".to_string(), 1),
-                ("WORKDIR".to_string(), 1),
-            ]),
-        };
+    #[test]
+    fn test_escape_char() {
+        let analysis = analyze_dockerfile(SINGLE_STAGE_DOCKERFILE).unwrap();
+        assert_eq!(analysis.escape_char, '\\');
+
+        let dockerfile = "# escape=`\nFROM mcr.microsoft.com/windows/servercore:ltsc2022\nRUN New-Item -ItemType Directory `\n    -Path C:\\app\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(analysis.escape_char, '`');
+        assert_eq!(analysis.instructions.total_count, 2);
+        assert_eq!(analysis.ordered_instructions[1].line, 3);
+    }
 
-        let env_vars = HashMap::from([("NODE_ENV".into(), "production".into())]);
+    #[test]
+    fn test_final_base_chain() {
+        let analysis = analyze_dockerfile(SINGLE_STAGE_DOCKERFILE).unwrap();
+        assert_eq!(analysis.final_base_chain.len(), 1);
 
-        let expected = models::Analysis {
-            num_stages: 1,
-            stage_names: vec![],
-            images,
-            copy_from_stages: vec![],
-            add_from_stages: vec![],
-            multistage_analysis: msa,
-            exposed_ports: vec!["3000".to_string()],
-            instructions,
-            args: HashMap::new(),
-            labels: HashMap::new(),
-            env_vars,
-        };
-        let res = analyze_dockerfile(dockerfile);
-        assert!(res.is_ok());
-        assert_eq!(res.unwrap(), expected);
+        let dockerfile = "FROM scratch AS rootfs\nCOPY rootfs /\nFROM rootfs AS app\nFROM app\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(analysis.final_base_chain, vec!["app", "rootfs", "scratch"]);
     }
 
     #[test]
-    fn test_multistage_with_copy_and_add() {
+    fn test_is_windows() {
         let dockerfile = r#"
-# Stage 1: Build dependencies and tools
-FROM node:20-alpine AS dependencies
-WORKDIR /app
-COPY package*.json ./
-RUN npm ci --only=production && \
-    npm cache clean --force
-
-# Stage 2: Build the application
-FROM node:20-alpine AS builder
-WORKDIR /app
-COPY package*.json ./
-RUN npm ci
-COPY src/ ./src/
-COPY public/ ./public/
-COPY tsconfig.json ./
-RUN npm run build
+FROM mcr.microsoft.com/windows/servercore:ltsc2022 AS base
+WORKDIR C:\app
 
-# Stage 3: Create configuration and assets
-FROM alpine:3.18 AS config-builder
-WORKDIR /configs
-RUN echo "server.port=8080" > app.properties && \
-    echo "database.host=localhost" >> app.properties && \
-    echo "Generated config" > app.conf && \
-    mkdir -p assets && \
-    echo "Asset file content" > assets/data.txt
+FROM base
+COPY app.exe .
+"#;
+        assert!(analyze_dockerfile(dockerfile).unwrap().is_windows);
 
-# Stage 4: Final production image
-FROM node:20-alpine AS production
-WORKDIR /app
+        let dockerfile = "FROM mcr.microsoft.com/dotnet/sdk:8.0-nanoserver-ltsc2022\n";
+        assert!(analyze_dockerfile(dockerfile).unwrap().is_windows);
 
-# Create non-root user
-RUN addgroup -g 1001 -S nodejs && \
-    adduser -S nextjs -u 1001
+        let dockerfile = "FROM example.com/tools:1.0\nSHELL [\"powershell\", \"-Command\"]\n";
+        assert!(analyze_dockerfile(dockerfile).unwrap().is_windows);
 
-# Copy production dependencies from stage 1 using COPY --from
-COPY --from=dependencies /app/node_modules ./node_modules
+        let dockerfile = "FROM alpine:3.20\nRUN apk add --no-cache curl\n";
+        assert!(!analyze_dockerfile(dockerfile).unwrap().is_windows);
+    }
 
-# Copy built application from stage 2 using COPY --from
-COPY --from=builder /app/dist ./dist
-COPY --from=builder /app/public ./public
+    #[test]
+    fn test_path_modifications() {
+        let analysis = analyze_dockerfile(MULTISTAGE_DOCKERFILE).unwrap();
+        assert_eq!(
+            analysis.path_modifications,
+            vec!["/home/appuser/.local/bin:$PATH"]
+        );
+
+        let dockerfile = "FROM golang:1.22\nENV PATH=/usr/local/go/bin:$PATH GOPATH=/go\nENV GOFLAGS=-mod=mod\nFROM alpine\nENV PATH /opt/app/bin:$PATH\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(
+            analysis.path_modifications,
+            vec!["/usr/local/go/bin:$PATH", "/opt/app/bin:$PATH"]
+        );
+    }
 
-# Copy configuration files from stage 3 using ADD --from
-# Note: ADD --from can be used similarly to COPY --from
-ADD --from=config-builder /configs/app.properties ./config/
-ADD --from=config-builder /configs/app.conf ./config/
-ADD --from=config-builder /configs/assets ./assets/
+    #[test]
+    fn test_file_metrics() {
+        let dockerfile = "# syntax=docker/dockerfile:1\n\n# Build\nFROM alpine:3.20\nRUN apk add --no-cache \\\n    curl\n   \n  # Run\nCMD [\"sh\"]\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(
+            analysis.file_metrics,
+            models::FileMetrics {
+                total_lines: 9,
+                comment_lines: 3,
+                blank_lines: 2,
+                instruction_lines: 4,
+            }
+        );
+    }
 
-# Copy application files
-COPY package*.json ./
-COPY server.js ./
+    #[test]
+    fn test_by_type_per_stage() {
+        let dockerfile = "ARG VERSION=3.20\nFROM golang:1.22 AS builder\nWORKDIR /src\nCOPY . .\nRUN go build -o /app .\nFROM alpine:${VERSION}\nCOPY --from=builder /app /app\nCMD [\"/app\"]\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(analysis.instructions.by_type_per_stage, None);
+
+        let options = models::AnalysisOptions {
+            instruction_counts_per_stage: true,
+            ..Default::default()
+        };
+        let analysis = analyze_dockerfile_with_options(dockerfile, &options).unwrap();
+        assert_eq!(
+            analysis.instructions.by_type_per_stage,
+            Some(vec![
+                HashMap::from([
+                    ("FROM".to_string(), 1),
+                    ("WORKDIR".to_string(), 1),
+                    ("COPY".to_string(), 1),
+                    ("RUN".to_string(), 1),
+                ]),
+                HashMap::from([
+                    ("FROM".to_string(), 1),
+                    ("COPY".to_string(), 1),
+                    ("CMD".to_string(), 1),
+                ]),
+            ])
+        );
+    }
 
-# Set ownership
-RUN chown -R nextjs:nodejs /app
-USER nextjs
+    #[test]
+    fn test_external_images_and_stage_base_references() {
+        let analysis = analyze_dockerfile(MULTISTAGE_DOCKERFILE).unwrap();
+        let external: Vec<String> = analysis
+            .external_images()
+            .into_iter()
+            .map(|image| image.full)
+            .collect();
+        assert_eq!(
+            external,
+            vec![
+                "docker.abc.com/base-images/python:3.13-debian@sha256:55f1d15ef4c37870e23c03e89ad238940b55c8ede9f13fac4b7d71c7955f1053"
+            ]
+        );
+        let stage_refs: Vec<String> = analysis
+            .stage_base_references()
+            .into_iter()
+            .map(|image| image.full)
+            .collect();
+        assert_eq!(stage_refs, vec!["base"]);
+    }
 
-# Expose port
-EXPOSE 8080
+    #[test]
+    fn test_external_references_keep_case() {
+        let dockerfile = r#"
+FROM golang:1.22-Alpine AS Builder
+RUN go build -o /app .
 
-# Health check
-HEALTHCHECK --interval=30s --timeout=3s --start-period=5s --retries=3 \
-    CMD curl -f http://localhost:8080/health || exit 1
+FROM Builder AS test
+RUN go test ./...
 
-# Start the application
-CMD ["node", "server.js"]
+FROM alpine:3.20
+COPY --from=BUILDER /app /app
+COPY --from=ghcr.io/MyOrg/tools:V1 /bin/tool /bin/tool
 "#;
-
-        let msa = models::MultistageAnalysis {
-            is_multistage: true,
-            stages_used_as_base_images: vec![],
-            stages_copied_from: vec!["builder".to_string(), "dependencies".to_string()],
-            stages_added_from: vec!["config-builder".to_string()],
-            unused_stages: vec!["production".to_string()],
-        };
-        let images: Vec<models::Image> = vec![
-            models::Image {
-                full: "alpine:3.18".to_string(),
-                components: Some(models::ImageComponents {
-                    registry: None,
-                    name: "alpine".to_string(),
-                    tag: Some("3.18".to_string()),
-                    digest: None,
-                }),
-            },
-            models::Image {
-                full: "node:20-alpine".to_string(),
-                components: Some(models::ImageComponents {
-                    registry: None,
-                    name: "node".to_string(),
-                    tag: Some("20-alpine".to_string()),
-                    digest: None,
-                }),
-            },
-        ];
-        let instructions = models::InstructionStats {
-            total_count: 31,
-            by_type: HashMap::from([
-                ("ADD".to_string(), 3),
-                ("CMD".to_string(), 1),
-                ("COPY".to_string(), 10),
-                ("EXPOSE".to_string(), 1),
-                ("FROM".to_string(), 4),
-                ("HEALTHCHECK".to_string(), 1),
-                ("RUN".to_string(), 6),
-                ("USER".to_string(), 1),
-                ("WORKDIR".to_string(), 4),
-            ]),
-        };
-
-        let expected = models::Analysis {
-            num_stages: 4,
-            stage_names: vec![
-                "builder".to_string(),
-                "config-builder".to_string(),
-                "dependencies".to_string(),
-                "production".to_string(),
-            ],
-            images,
-            copy_from_stages: vec!["builder".to_string(), "dependencies".to_string()],
-            add_from_stages: vec!["config-builder".to_string()],
-            multistage_analysis: msa,
-            exposed_ports: vec!["8080".to_string()],
-            instructions,
-            args: HashMap::new(),
-            labels: HashMap::new(),
-            env_vars: HashMap::new(),
-        };
-        let res = analyze_dockerfile(dockerfile);
-        assert!(res.is_ok());
-        assert_eq!(res.unwrap(), expected);
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(
+            analysis.copy_from_stages,
+            vec!["builder", "ghcr.io/MyOrg/tools:V1"]
+        );
+        let images: Vec<&str> = analysis.images.iter().map(|i| i.full.as_str()).collect();
+        assert_eq!(images, vec!["alpine:3.20", "builder", "golang:1.22-Alpine"]);
+        assert_eq!(analysis.stages[0].base_image, "golang:1.22-Alpine");
+        assert_eq!(analysis.stages[1].depends_on, vec![0]);
+        assert_eq!(analysis.stages[2].depends_on, vec![0]);
+        assert_eq!(
+            analysis.multistage_analysis.stages_copied_from,
+            vec!["builder"]
+        );
     }
 
     #[test]
-    fn test_multistage_with_copy_and_add2() {
+    fn test_flags_used() {
+        let analysis = analyze_dockerfile(MULTISTAGE_DOCKERFILE).unwrap();
+        assert_eq!(analysis.flags_used, HashMap::from([("chown".into(), 3)]));
+
         let dockerfile = r#"
-# Stage 1: Download and prepare external dependencies
-FROM alpine:3.18 AS downloader
-RUN apk add --no-cache curl tar
-WORKDIR /downloads
-RUN curl -L https://github.com/some-project/releases/download/v1.0.0/binary.tar.gz -o binary.tar.gz && \
-    tar -xzf binary.tar.gz
+FROM --platform=$BUILDPLATFORM golang:1.22 AS builder
+RUN --mount=type=cache,target=/root/.cache/go-build --network=none go build ./...
 
-# Stage 2: Compile application
-FROM golang:1.21-alpine AS go-builder
-WORKDIR /src
-COPY go.mod go.sum ./
-RUN go mod download
-COPY . .
-RUN CGO_ENABLED=0 GOOS=linux go build -o app .
+FROM alpine
+COPY --from=builder --chown=app:app --link /app /app
+ADD --checksum=sha256:24454f830cdb571e2c4ad15481119c43b3cafd48dd869a9b2945d1036d1dc68d https://example.com/tool.tgz /tmp/
+HEALTHCHECK --interval=30s CMD wget -q -O- http://localhost/
+"#;
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(
+            analysis.flags_used,
+            HashMap::from([
+                ("platform".into(), 1),
+                ("mount".into(), 1),
+                ("network".into(), 1),
+                ("from".into(), 1),
+                ("chown".into(), 1),
+                ("link".into(), 1),
+                ("checksum".into(), 1),
+                ("interval".into(), 1),
+            ])
+        );
+    }
 
-# Stage 3: Generate certificates and configs
-FROM alpine:3.18 AS cert-generator
-RUN apk add --no-cache openssl
-WORKDIR /certs
-RUN openssl req -x509 -newkey rsa:4096 -keyout key.pem -out cert.pem -days 365 -nodes \
-    -subj "/C=US/ST=State/L=City/O=Organization/CN=localhost"
-RUN echo "tls_cert=/app/certs/cert.pem" > server.conf && \
-    echo "tls_key=/app/certs/key.pem" >> server.conf
+    #[test]
+    fn test_created_users() {
+        let analysis = analyze_dockerfile(SINGLE_STAGE_DOCKERFILE).unwrap();
+        assert_eq!(analysis.created_users, vec!["nextjs"]);
 
-# Stage 4: Final runtime image
-FROM alpine:3.18
-RUN apk add --no-cache ca-certificates tzdata
-WORKDIR /app
+        let dockerfile = "FROM debian\nRUN groupadd -r app && useradd -r -g app -d /srv --shell=/bin/false app\nRUN sudo adduser --system --ingroup staff --gecos 'Build user' builder staff\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(analysis.created_users, vec!["app", "builder"]);
+    }
 
-# Copy binary from Go builder stage
-COPY --from=go-builder /src/app ./
+    #[test]
+    fn test_registries() {
+        let analysis = analyze_dockerfile(MULTISTAGE_DOCKERFILE).unwrap();
+        assert_eq!(analysis.registries, vec!["docker.abc.com"]);
 
-# Copy external dependencies using ADD --from
-ADD --from=downloader /downloads/binary ./bin/
-ADD --from=downloader /downloads/config/ ./external-config/
+        let dockerfile = "FROM docker.abc.com/base-images/python:3.13 AS base\nFROM node:20-alpine AS assets\nFROM base\nCOPY --from=assets /app/dist /static\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(analysis.registries, vec!["docker.abc.com", "docker.io"]);
 
-# Copy certificates and config using COPY --from
-COPY --from=cert-generator /certs/*.pem ./certs/
-COPY --from=cert-generator /certs/server.conf ./config/
+        let dockerfile = "FROM ghcr.io/org/tools:1 AS tools\nFROM tools\nCOPY --from=quay.io/org/cli:2 /cli /usr/bin/\nCOPY --from=0 /bin /bin\nFROM scratch\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(analysis.registries, vec!["ghcr.io", "quay.io"]);
+    }
 
-# Create necessary directories and set permissions
-RUN mkdir -p /app/logs && \
-    adduser -D -s /bin/sh appuser && \
-    chown -R appuser:appuser /app
+    #[test]
+    fn test_copy_from_images() {
+        let dockerfile = "FROM golang:1.22 AS build\nFROM alpine:3.20\nCOPY --from=build /app /app\nCOPY --from=registry.example.com:5000/tools/nginx:1.25 /etc/nginx /etc/nginx\nCOPY --from=0 /go/bin /usr/bin\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(
+            analysis.copy_from_images,
+            vec![models::Image {
+                full: "registry.example.com:5000/tools/nginx:1.25".to_string(),
+                components: Some(models::ImageComponents {
+                    registry: Some("registry.example.com:5000".to_string()),
+                    name: "tools/nginx".to_string(),
+                    tag: Some("1.25".to_string()),
+                    digest: None,
+                }),
+            }]
+        );
+        assert!(
+            analyze_dockerfile(MULTISTAGE_DOCKERFILE)
+                .unwrap()
+                .copy_from_images
+                .is_empty()
+        );
+    }
 
-USER appuser
-EXPOSE 8080 8443
+    #[test]
+    fn test_maintainer() {
+        let dockerfile = "FROM alpine\nMAINTAINER Jane Doe <jane@example.com>\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(
+            analysis.maintainer.as_deref(),
+            Some("Jane Doe <jane@example.com>")
+        );
+        assert_eq!(
+            analyze_dockerfile("FROM alpine\n").unwrap().maintainer,
+            None
+        );
+    }
 
-CMD ["./app"]
+    #[test]
+    fn test_stop_signal() {
+        let dockerfile = r#"
+FROM nginx:1.25 AS base
+STOPSIGNAL SIGQUIT
+
+FROM golang:1.22 AS builder
+STOPSIGNAL banana
+
+FROM base
 "#;
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        assert_eq!(analysis.stop_signal.as_deref(), Some("SIGQUIT"));
 
-        let msa = models::MultistageAnalysis {
-            is_multistage: true,
-            stages_used_as_base_images: vec![],
-            stages_copied_from: vec!["cert-generator".to_string(), "go-builder".to_string()],
-            stages_added_from: vec!["downloader".to_string()],
-            unused_stages: vec![],
-        };
-        let images: Vec<models::Image> = vec![
-            models::Image {
-                full: "alpine:3.18".to_string(),
-                components: Some(models::ImageComponents {
-                    registry: None,
-                    name: "alpine".to_string(),
-                    tag: Some("3.18".to_string()),
-                    digest: None,
-                }),
-            },
-            models::Image {
-                full: "golang:1.21-alpine".to_string(),
-                components: Some(models::ImageComponents {
-                    registry: None,
-                    name: "golang".to_string(),
-                    tag: Some("1.21-alpine".to_string()),
-                    digest: None,
-                }),
-            },
-        ];
-        let instructions = models::InstructionStats {
-            total_count: 27,
-            by_type: HashMap::from([
-                ("ADD".to_string(), 2),
-                ("CMD".to_string(), 1),
-                ("COPY".to_string(), 5),
-                ("EXPOSE".to_string(), 1),
-                ("FROM".to_string(), 4),
-                ("RUN".to_string(), 9),
-                ("USER".to_string(), 1),
-                ("WORKDIR".to_string(), 4),
-            ]),
-        };
+        let analysis = analyze_dockerfile("FROM alpine\nSTOPSIGNAL banana\n").unwrap();
+        assert_eq!(analysis.stop_signal.as_deref(), Some("banana"));
 
-        let expected = models::Analysis {
-            num_stages: 4,
-            stage_names: vec![
-                "cert-generator".to_string(),
-                "downloader".to_string(),
-                "go-builder".to_string(),
-            ],
-            images,
-            copy_from_stages: vec!["cert-generator".to_string(), "go-builder".to_string()],
-            add_from_stages: vec!["downloader".to_string()],
-            multistage_analysis: msa,
-            exposed_ports: vec!["8080".to_string(), "8443".to_string()],
-            instructions,
-            args: HashMap::new(),
-            labels: HashMap::new(),
-            env_vars: HashMap::new(),
-        };
+        let analysis = analyze_dockerfile(SINGLE_STAGE_DOCKERFILE).unwrap();
+        assert_eq!(analysis.stop_signal, None);
+    }
+
+    #[test]
+    fn test_effective_env_excludes_unrelated_stages() {
+        let dockerfile = r#"
+FROM golang:1.21-alpine AS builder
+ENV CGO_ENABLED=0 GOOS=linux
+RUN go build -o /app .
+
+FROM alpine:3.18 AS runtime-base
+ENV TZ=UTC
+
+FROM runtime-base
+ENV APP_ENV=production
+COPY --from=builder /app /app
+"#;
         let res = analyze_dockerfile(dockerfile);
         assert!(res.is_ok());
-        assert_eq!(res.unwrap(), expected);
+        let analysis = res.unwrap();
+        assert_eq!(
+            analysis.effective_env,
+            HashMap::from([
+                ("TZ".into(), "UTC".into()),
+                ("APP_ENV".into(), "production".into()),
+            ])
+        );
+        assert!(analysis.env_vars.contains_key("CGO_ENABLED"));
+    }
+
+    #[test]
+    fn test_effective_env_inherits_through_base_stage() {
+        let dockerfile = r#"
+FROM golang:1.21-alpine AS builder
+ENV CGO_ENABLED=0 GOOS=linux
+
+FROM builder
+ENV GOOS=darwin
+"#;
+        let res = analyze_dockerfile(dockerfile);
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap().effective_env,
+            HashMap::from([
+                ("CGO_ENABLED".into(), "0".into()),
+                ("GOOS".into(), "darwin".into()),
+            ])
+        );
     }
 
     #[cfg(test)]
@@ -734,11 +4536,31 @@ CMD ["./app"]
         use super::*;
         use std::vec;
 
+        const COMPLEX_DEPENDENCY_CHAIN_DOCKERFILE: &str = r#"
+FROM alpine:3.18 AS source
+RUN echo "source data" > /data.txt
+
+FROM ubuntu:20.04 AS processor
+COPY --from=source /data.txt ./
+RUN cat data.txt > processed.txt
+
+FROM node:18-alpine AS builder
+ADD --from=processor /processed.txt ./
+COPY . .
+RUN npm run build
+
+FROM nginx:alpine
+COPY --from=builder /app/dist /usr/share/nginx/html
+ADD --from=source /data.txt /usr/share/nginx/html/
+"#;
+
         #[test]
         fn test_empty_dockerfile() {
             let dockerfile = "";
             let res = analyze_dockerfile(dockerfile);
-            assert!(res.is_err());
+            assert_eq!(res.unwrap_err().to_string(), "Dockerfile is empty");
+            let res = analyze_dockerfile("  \n\t\n");
+            assert_eq!(res.unwrap_err().to_string(), "Dockerfile is empty");
         }
 
         #[test]
@@ -748,7 +4570,10 @@ CMD ["./app"]
 # Another comment
         "#;
             let res = analyze_dockerfile(dockerfile);
-            assert!(res.is_err());
+            assert_eq!(
+                res.unwrap_err().to_string(),
+                "Dockerfile contains no instructions, only comments"
+            );
         }
 
         #[test]
@@ -769,10 +4594,12 @@ cmd ["nginx", "-g", "daemon off;"]
 
             let msa = models::MultistageAnalysis {
                 is_multistage: true,
+                has_multiple_stages: true,
                 stages_used_as_base_images: vec![],
                 stages_copied_from: vec!["builder".to_string()],
                 stages_added_from: vec![],
                 unused_stages: vec![],
+                artifact_only_stages: vec!["builder".to_string()],
             };
             let images: Vec<models::Image> = vec![
                 models::Image {
@@ -805,6 +4632,7 @@ cmd ["nginx", "-g", "daemon off;"]
                     ("RUN".to_string(), 2),
                     ("WORKDIR".to_string(), 1),
                 ]),
+                by_type_per_stage: None,
             };
 
             let expected = models::Analysis {
@@ -819,6 +4647,168 @@ cmd ["nginx", "-g", "daemon off;"]
                 args: HashMap::new(),
                 labels: HashMap::new(),
                 env_vars: HashMap::new(),
+                escape_char: '\\',
+                final_base_chain: vec!["nginx:alpine".to_string()],
+                distinct_base_images: 2,
+                registries: vec!["docker.io".to_string()],
+                stages: vec![
+                    models::StageAnalysis {
+                        index: 0,
+                        name: Some("builder".to_string()),
+                        base_image: "node:18-alpine".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                    models::StageAnalysis {
+                        index: 1,
+                        name: None,
+                        base_image: "nginx:alpine".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![0],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec!["80".to_string()],
+                        entrypoint: None,
+                    },
+                ],
+                parsed_ports: vec![models::ExposedPort {
+                    port: 80,
+                    protocol: "tcp".to_string(),
+                }],
+                flags_used: HashMap::from([("from".to_string(), 1)]),
+                file_metrics: models::FileMetrics {
+                    total_lines: 12,
+                    comment_lines: 0,
+                    blank_lines: 2,
+                    instruction_lines: 10,
+                },
+                per_stage_distinct_instruction_kinds: vec![4, 4],
+                ordered_instructions: vec![
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "node:18-alpine as builder".to_string(),
+                        stage_index: 0,
+                        line: 2,
+                    },
+                    models::InstructionEntry {
+                        keyword: "WORKDIR".to_string(),
+                        arguments: "/app".to_string(),
+                        stage_index: 0,
+                        line: 3,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: "package*.json ./".to_string(),
+                        stage_index: 0,
+                        line: 4,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "npm install".to_string(),
+                        stage_index: 0,
+                        line: 5,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: ". .".to_string(),
+                        stage_index: 0,
+                        line: 6,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "npm run build".to_string(),
+                        stage_index: 0,
+                        line: 7,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "nginx:alpine".to_string(),
+                        stage_index: 1,
+                        line: 9,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: "--from=builder /app/dist /usr/share/nginx/html".to_string(),
+                        stage_index: 1,
+                        line: 10,
+                    },
+                    models::InstructionEntry {
+                        keyword: "EXPOSE".to_string(),
+                        arguments: "80".to_string(),
+                        stage_index: 1,
+                        line: 11,
+                    },
+                    models::InstructionEntry {
+                        keyword: "CMD".to_string(),
+                        arguments: "[\"nginx\", \"-g\", \"daemon off;\"]".to_string(),
+                        stage_index: 1,
+                        line: 12,
+                    },
+                ],
+                copy_operations: vec![
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec!["package*.json".to_string()],
+                        destination: "./".to_string(),
+                        stage_index: 0,
+                        line: 4,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: None,
+                        checksum: None,
+                        link: false,
+                    },
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec![".".to_string()],
+                        destination: ".".to_string(),
+                        stage_index: 0,
+                        line: 6,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: None,
+                        checksum: None,
+                        link: false,
+                    },
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec!["/app/dist".to_string()],
+                        destination: "/usr/share/nginx/html".to_string(),
+                        stage_index: 1,
+                        line: 10,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: Some("builder".to_string()),
+                        checksum: None,
+                        link: false,
+                    },
+                ],
+                run_operations: vec![
+                    models::RunOperation {
+                        command: "npm install".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 0,
+                        line: 5,
+                    },
+                    models::RunOperation {
+                        command: "npm run build".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 0,
+                        line: 7,
+                    },
+                ],
+                quality_score: 100,
+                ..Default::default()
             };
 
             let res = analyze_dockerfile(dockerfile);
@@ -844,10 +4834,12 @@ CMD ["./app"]
 
             let msa = models::MultistageAnalysis {
                 is_multistage: true,
+                has_multiple_stages: true,
                 stages_used_as_base_images: vec!["base".to_string()],
                 stages_copied_from: vec!["builder".to_string()],
                 stages_added_from: vec![],
                 unused_stages: vec![],
+                artifact_only_stages: vec!["builder".to_string()],
             };
             let images: Vec<models::Image> = vec![
                 models::Image {
@@ -878,6 +4870,7 @@ CMD ["./app"]
                     ("RUN".to_string(), 2),
                     ("WORKDIR".to_string(), 1),
                 ]),
+                by_type_per_stage: None,
             };
 
             let env_vars = HashMap::new();
@@ -894,11 +4887,175 @@ CMD ["./app"]
                 args: HashMap::new(),
                 labels: HashMap::new(),
                 env_vars,
+                escape_char: '\\',
+                final_base_chain: vec!["base".to_string(), "ubuntu:20.04".to_string()],
+                distinct_base_images: 1,
+                registries: vec!["docker.io".to_string()],
+                stages: vec![
+                    models::StageAnalysis {
+                        index: 0,
+                        name: Some("base".to_string()),
+                        base_image: "ubuntu:20.04".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                    models::StageAnalysis {
+                        index: 1,
+                        name: Some("builder".to_string()),
+                        base_image: "base".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![0],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                    models::StageAnalysis {
+                        index: 2,
+                        name: None,
+                        base_image: "base".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![0, 1],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                ],
+                flags_used: HashMap::from([("from".to_string(), 1)]),
+                file_metrics: models::FileMetrics {
+                    total_lines: 12,
+                    comment_lines: 0,
+                    blank_lines: 3,
+                    instruction_lines: 9,
+                },
+                per_stage_distinct_instruction_kinds: vec![3, 3, 3],
+                ordered_instructions: vec![
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "ubuntu:20.04 AS base".to_string(),
+                        stage_index: 0,
+                        line: 2,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "apt-get update && apt-get install -y curl".to_string(),
+                        stage_index: 0,
+                        line: 3,
+                    },
+                    models::InstructionEntry {
+                        keyword: "WORKDIR".to_string(),
+                        arguments: "/app".to_string(),
+                        stage_index: 0,
+                        line: 4,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "base AS builder".to_string(),
+                        stage_index: 1,
+                        line: 6,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: ". .".to_string(),
+                        stage_index: 1,
+                        line: 7,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "make build".to_string(),
+                        stage_index: 1,
+                        line: 8,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "base".to_string(),
+                        stage_index: 2,
+                        line: 10,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: "--from=builder /app/dist ./".to_string(),
+                        stage_index: 2,
+                        line: 11,
+                    },
+                    models::InstructionEntry {
+                        keyword: "CMD".to_string(),
+                        arguments: "[\"./app\"]".to_string(),
+                        stage_index: 2,
+                        line: 12,
+                    },
+                ],
+                copy_operations: vec![
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec![".".to_string()],
+                        destination: ".".to_string(),
+                        stage_index: 1,
+                        line: 7,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: None,
+                        checksum: None,
+                        link: false,
+                    },
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec!["/app/dist".to_string()],
+                        destination: "./".to_string(),
+                        stage_index: 2,
+                        line: 11,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: Some("builder".to_string()),
+                        checksum: None,
+                        link: false,
+                    },
+                ],
+                run_operations: vec![
+                    models::RunOperation {
+                        command: "apt-get update && apt-get install -y curl".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 0,
+                        line: 3,
+                    },
+                    models::RunOperation {
+                        command: "make build".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 1,
+                        line: 8,
+                    },
+                ],
+                findings: vec![
+                    models::Finding {
+                        rule_id: "apt-lists-not-cleaned".to_string(),
+                        severity: "info".to_string(),
+                        message: "RUN installs packages with apt but does not remove /var/lib/apt/lists in the same step; add '&& rm -rf /var/lib/apt/lists/*' to keep the layer small".to_string(),
+                        line: Some(3),
+                    },
+                ],
+                quality_score: 98,
+                quality_deductions: HashMap::from([("apt-lists-not-cleaned".to_string(), 2)]),
+                ..Default::default()
             };
 
             let res = analyze_dockerfile(dockerfile);
             assert!(res.is_ok());
-            assert_eq!(res.unwrap(), expected);
+            let analysis = res.unwrap();
+            assert_eq!(analysis, expected);
+            assert_eq!(analysis.final_base_chain, vec!["base", "ubuntu:20.04"]);
         }
 
         #[test]
@@ -916,10 +5073,12 @@ COPY --from=builder /app/dist /usr/share/nginx/html
 
             let msa = models::MultistageAnalysis {
                 is_multistage: true,
+                has_multiple_stages: true,
                 stages_used_as_base_images: vec![],
                 stages_copied_from: vec!["builder".to_string()],
                 stages_added_from: vec![],
                 unused_stages: vec![],
+                artifact_only_stages: vec!["builder".to_string()],
             };
             let images: Vec<models::Image> = vec![
                 models::Image {
@@ -945,6 +5104,7 @@ COPY --from=builder /app/dist /usr/share/nginx/html
                     ("RUN".to_string(), 1),
                     ("WORKDIR".to_string(), 1),
                 ]),
+                by_type_per_stage: None,
             };
             let args = HashMap::from([("BASE_IMAGE".into(), Some("node:18-alpine".into()))]);
 
@@ -960,6 +5120,141 @@ COPY --from=builder /app/dist /usr/share/nginx/html
                 args,
                 labels: HashMap::new(),
                 env_vars: HashMap::new(),
+                escape_char: '\\',
+                final_base_chain: vec!["nginx:alpine".to_string()],
+                distinct_base_images: 2,
+                registries: vec!["docker.io".to_string()],
+                stages: vec![
+                    models::StageAnalysis {
+                        index: 0,
+                        name: Some("builder".to_string()),
+                        base_image: "$BASE_IMAGE".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                    models::StageAnalysis {
+                        index: 1,
+                        name: None,
+                        base_image: "nginx:alpine".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![0],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                ],
+                flags_used: HashMap::from([("from".to_string(), 1)]),
+                file_metrics: models::FileMetrics {
+                    total_lines: 9,
+                    comment_lines: 0,
+                    blank_lines: 2,
+                    instruction_lines: 7,
+                },
+                per_stage_distinct_instruction_kinds: vec![4, 2],
+                ordered_instructions: vec![
+                    models::InstructionEntry {
+                        keyword: "ARG".to_string(),
+                        arguments: "BASE_IMAGE=node:18-alpine".to_string(),
+                        stage_index: 0,
+                        line: 2,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "$BASE_IMAGE AS builder".to_string(),
+                        stage_index: 0,
+                        line: 3,
+                    },
+                    models::InstructionEntry {
+                        keyword: "WORKDIR".to_string(),
+                        arguments: "/app".to_string(),
+                        stage_index: 0,
+                        line: 4,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: ". .".to_string(),
+                        stage_index: 0,
+                        line: 5,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "npm run build".to_string(),
+                        stage_index: 0,
+                        line: 6,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "nginx:alpine".to_string(),
+                        stage_index: 1,
+                        line: 8,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: "--from=builder /app/dist /usr/share/nginx/html".to_string(),
+                        stage_index: 1,
+                        line: 9,
+                    },
+                ],
+                copy_operations: vec![
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec![".".to_string()],
+                        destination: ".".to_string(),
+                        stage_index: 0,
+                        line: 5,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: None,
+                        checksum: None,
+                        link: false,
+                    },
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec!["/app/dist".to_string()],
+                        destination: "/usr/share/nginx/html".to_string(),
+                        stage_index: 1,
+                        line: 9,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: Some("builder".to_string()),
+                        checksum: None,
+                        link: false,
+                    },
+                ],
+                run_operations: vec![
+                    models::RunOperation {
+                        command: "npm run build".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 0,
+                        line: 6,
+                    },
+                ],
+                global_args: HashMap::from([
+                    ("BASE_IMAGE".to_string(), Some("node:18-alpine".to_string())),
+                ]),
+                arg_usages: HashMap::from([("BASE_IMAGE".to_string(), vec!["FROM".to_string()])]),
+                arg_count: 1,
+                findings: vec![
+                    models::Finding {
+                        rule_id: "missing-cmd".to_string(),
+                        severity: "info".to_string(),
+                        message: "The final stage defines no CMD or ENTRYPOINT, so the image runs nothing by default unless the base image nginx:alpine provides one".to_string(),
+                        line: None,
+                    },
+                ],
+                quality_score: 98,
+                quality_deductions: HashMap::from([("missing-cmd".to_string(), 2)]),
+                ..Default::default()
             };
 
             let res = analyze_dockerfile(dockerfile);
@@ -1008,10 +5303,12 @@ COPY --from=builder /app/dist ./
 
             let msa = models::MultistageAnalysis {
                 is_multistage: true,
+                has_multiple_stages: true,
                 stages_used_as_base_images: vec!["base".to_string()],
                 stages_copied_from: vec!["builder".to_string()],
                 stages_added_from: vec![],
                 unused_stages: vec![],
+                artifact_only_stages: vec!["builder".to_string()],
             };
             let images: Vec<models::Image> = vec![
                 models::Image {
@@ -1040,6 +5337,7 @@ COPY --from=builder /app/dist ./
                     ("FROM".to_string(), 3),
                     ("RUN".to_string(), 3),
                 ]),
+                by_type_per_stage: None,
             };
 
             let expected = models::Analysis {
@@ -1054,6 +5352,208 @@ COPY --from=builder /app/dist ./
                 args: HashMap::new(),
                 labels: HashMap::new(),
                 env_vars: HashMap::new(),
+                escape_char: '\\',
+                final_base_chain: vec!["base".to_string(), "ubuntu:20.04".to_string()],
+                distinct_base_images: 1,
+                registries: vec!["docker.io".to_string()],
+                stages: vec![
+                    models::StageAnalysis {
+                        index: 0,
+                        name: Some("base".to_string()),
+                        base_image: "ubuntu:20.04".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                    models::StageAnalysis {
+                        index: 1,
+                        name: Some("builder".to_string()),
+                        base_image: "base".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![0, 1],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                    models::StageAnalysis {
+                        index: 2,
+                        name: None,
+                        base_image: "base".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![0, 1],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                ],
+                flags_used: HashMap::from([("from".to_string(), 2)]),
+                file_metrics: models::FileMetrics {
+                    total_lines: 13,
+                    comment_lines: 1,
+                    blank_lines: 3,
+                    instruction_lines: 9,
+                },
+                per_stage_distinct_instruction_kinds: vec![2, 3, 2],
+                ordered_instructions: vec![
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "ubuntu:20.04 AS base".to_string(),
+                        stage_index: 0,
+                        line: 2,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "apt-get update".to_string(),
+                        stage_index: 0,
+                        line: 3,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "base AS builder".to_string(),
+                        stage_index: 1,
+                        line: 5,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: ". .".to_string(),
+                        stage_index: 1,
+                        line: 6,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "make build".to_string(),
+                        stage_index: 1,
+                        line: 7,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: "--from=builder /app/temp ./temp".to_string(),
+                        stage_index: 1,
+                        line: 9,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "process_temp".to_string(),
+                        stage_index: 1,
+                        line: 10,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "base".to_string(),
+                        stage_index: 2,
+                        line: 12,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: "--from=builder /app/dist ./".to_string(),
+                        stage_index: 2,
+                        line: 13,
+                    },
+                ],
+                copy_operations: vec![
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec![".".to_string()],
+                        destination: ".".to_string(),
+                        stage_index: 1,
+                        line: 6,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: None,
+                        checksum: None,
+                        link: false,
+                    },
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec!["/app/temp".to_string()],
+                        destination: "./temp".to_string(),
+                        stage_index: 1,
+                        line: 9,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: Some("builder".to_string()),
+                        checksum: None,
+                        link: false,
+                    },
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec!["/app/dist".to_string()],
+                        destination: "./".to_string(),
+                        stage_index: 2,
+                        line: 13,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: Some("builder".to_string()),
+                        checksum: None,
+                        link: false,
+                    },
+                ],
+                run_operations: vec![
+                    models::RunOperation {
+                        command: "apt-get update".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 0,
+                        line: 3,
+                    },
+                    models::RunOperation {
+                        command: "make build".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 1,
+                        line: 7,
+                    },
+                    models::RunOperation {
+                        command: "process_temp".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 1,
+                        line: 10,
+                    },
+                ],
+                findings: vec![
+                    models::Finding {
+                        rule_id: "relative-copy-destination".to_string(),
+                        severity: "warning".to_string(),
+                        message: "COPY destination '.' is relative and no WORKDIR is set in the stage; it resolves to '/'".to_string(),
+                        line: Some(6),
+                    },
+                    models::Finding {
+                        rule_id: "relative-copy-destination".to_string(),
+                        severity: "warning".to_string(),
+                        message: "COPY destination './temp' is relative and no WORKDIR is set in the stage; it resolves to '/temp'".to_string(),
+                        line: Some(9),
+                    },
+                    models::Finding {
+                        rule_id: "relative-copy-destination".to_string(),
+                        severity: "warning".to_string(),
+                        message: "COPY destination './' is relative and no WORKDIR is set in the stage; it resolves to '/'".to_string(),
+                        line: Some(13),
+                    },
+                    models::Finding {
+                        rule_id: "missing-cmd".to_string(),
+                        severity: "info".to_string(),
+                        message: "The final stage defines no CMD or ENTRYPOINT, so the image runs nothing by default unless the base image ubuntu:20.04 provides one".to_string(),
+                        line: None,
+                    },
+                ],
+                quality_score: 68,
+                quality_deductions: HashMap::from([
+                    ("missing-cmd".to_string(), 2),
+                    ("relative-copy-destination".to_string(), 30),
+                ]),
+                ..Default::default()
             };
 
             let res = analyze_dockerfile(dockerfile);
@@ -1075,10 +5575,12 @@ RUN cat assets/config.json
 
             let msa = models::MultistageAnalysis {
                 is_multistage: true,
+                has_multiple_stages: true,
                 stages_used_as_base_images: vec![],
                 stages_copied_from: vec![],
                 stages_added_from: vec!["assets".to_string()],
                 unused_stages: vec![],
+                artifact_only_stages: vec!["assets".to_string()],
             };
             let images: Vec<models::Image> = vec![
                 models::Image {
@@ -1109,6 +5611,7 @@ RUN cat assets/config.json
                     ("RUN".to_string(), 2),
                     ("WORKDIR".to_string(), 1),
                 ]),
+                by_type_per_stage: None,
             };
 
             let expected = models::Analysis {
@@ -1123,6 +5626,141 @@ RUN cat assets/config.json
                 args: HashMap::new(),
                 labels: HashMap::new(),
                 env_vars: HashMap::new(),
+                escape_char: '\\',
+                final_base_chain: vec!["ubuntu:20.04".to_string()],
+                distinct_base_images: 2,
+                registries: vec!["docker.io".to_string()],
+                stages: vec![
+                    models::StageAnalysis {
+                        index: 0,
+                        name: Some("assets".to_string()),
+                        base_image: "alpine:3.18".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                    models::StageAnalysis {
+                        index: 1,
+                        name: None,
+                        base_image: "ubuntu:20.04".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![0],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                ],
+                flags_used: HashMap::from([("from".to_string(), 1)]),
+                file_metrics: models::FileMetrics {
+                    total_lines: 8,
+                    comment_lines: 0,
+                    blank_lines: 2,
+                    instruction_lines: 6,
+                },
+                per_stage_distinct_instruction_kinds: vec![3, 3],
+                ordered_instructions: vec![
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "alpine:3.18 AS assets".to_string(),
+                        stage_index: 0,
+                        line: 2,
+                    },
+                    models::InstructionEntry {
+                        keyword: "WORKDIR".to_string(),
+                        arguments: "/assets".to_string(),
+                        stage_index: 0,
+                        line: 3,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "echo \"config data\" > config.json".to_string(),
+                        stage_index: 0,
+                        line: 4,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "ubuntu:20.04".to_string(),
+                        stage_index: 1,
+                        line: 6,
+                    },
+                    models::InstructionEntry {
+                        keyword: "ADD".to_string(),
+                        arguments: "--from=assets /assets/ ./assets/".to_string(),
+                        stage_index: 1,
+                        line: 7,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "cat assets/config.json".to_string(),
+                        stage_index: 1,
+                        line: 8,
+                    },
+                ],
+                copy_operations: vec![
+                    models::CopyOperation {
+                        instruction: "ADD".to_string(),
+                        sources: vec!["/assets/".to_string()],
+                        destination: "./assets/".to_string(),
+                        stage_index: 1,
+                        line: 7,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: Some("assets".to_string()),
+                        checksum: None,
+                        link: false,
+                    },
+                ],
+                run_operations: vec![
+                    models::RunOperation {
+                        command: "echo \"config data\" > config.json".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 0,
+                        line: 4,
+                    },
+                    models::RunOperation {
+                        command: "cat assets/config.json".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 1,
+                        line: 8,
+                    },
+                ],
+                findings: vec![
+                    models::Finding {
+                        rule_id: "relative-copy-destination".to_string(),
+                        severity: "warning".to_string(),
+                        message: "ADD destination './assets/' is relative and no WORKDIR is set in the stage; it resolves to '/assets'".to_string(),
+                        line: Some(7),
+                    },
+                    models::Finding {
+                        rule_id: "prefer-copy".to_string(),
+                        severity: "warning".to_string(),
+                        message: "ADD of local files to './assets/' does nothing COPY doesn't; use COPY instead".to_string(),
+                        line: Some(7),
+                    },
+                    models::Finding {
+                        rule_id: "missing-cmd".to_string(),
+                        severity: "info".to_string(),
+                        message: "The final stage defines no CMD or ENTRYPOINT, so the image runs nothing by default unless the base image ubuntu:20.04 provides one".to_string(),
+                        line: None,
+                    },
+                ],
+                quality_score: 78,
+                quality_deductions: HashMap::from([
+                    ("missing-cmd".to_string(), 2),
+                    ("prefer-copy".to_string(), 10),
+                    ("relative-copy-destination".to_string(), 10),
+                ]),
+                ..Default::default()
             };
 
             let res = analyze_dockerfile(dockerfile);
@@ -1151,10 +5789,12 @@ CMD ["npm", "start"]
 
             let msa = models::MultistageAnalysis {
                 is_multistage: true,
+                has_multiple_stages: true,
                 stages_used_as_base_images: vec![],
                 stages_copied_from: vec!["builder".to_string()],
                 stages_added_from: vec![],
                 unused_stages: vec![],
+                artifact_only_stages: vec!["builder".to_string()],
             };
             let images: Vec<models::Image> = vec![models::Image {
                 full: "node:18-alpine".to_string(),
@@ -1175,6 +5815,7 @@ CMD ["npm", "start"]
                     ("RUN".to_string(), 1),
                     ("WORKDIR".to_string(), 2),
                 ]),
+                by_type_per_stage: None,
             };
 
             let expected = models::Analysis {
@@ -1189,6 +5830,150 @@ CMD ["npm", "start"]
                 args: HashMap::new(),
                 labels: HashMap::new(),
                 env_vars: HashMap::new(),
+                escape_char: '\\',
+                final_base_chain: vec!["node:18-alpine".to_string()],
+                distinct_base_images: 1,
+                reused_base_images: vec!["node:18-alpine".to_string()],
+                registries: vec!["docker.io".to_string()],
+                stages: vec![
+                    models::StageAnalysis {
+                        index: 0,
+                        name: Some("builder".to_string()),
+                        base_image: "node:18-alpine".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                    models::StageAnalysis {
+                        index: 1,
+                        name: None,
+                        base_image: "node:18-alpine".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![0],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                ],
+                flags_used: HashMap::from([("from".to_string(), 1)]),
+                file_metrics: models::FileMetrics {
+                    total_lines: 15,
+                    comment_lines: 4,
+                    blank_lines: 2,
+                    instruction_lines: 9,
+                },
+                per_stage_distinct_instruction_kinds: vec![4, 4],
+                ordered_instructions: vec![
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "node:18-alpine AS builder".to_string(),
+                        stage_index: 0,
+                        line: 3,
+                    },
+                    models::InstructionEntry {
+                        keyword: "WORKDIR".to_string(),
+                        arguments: "/app".to_string(),
+                        stage_index: 0,
+                        line: 4,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: "package*.json ./".to_string(),
+                        stage_index: 0,
+                        line: 6,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "npm ci".to_string(),
+                        stage_index: 0,
+                        line: 7,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "node:18-alpine".to_string(),
+                        stage_index: 1,
+                        line: 10,
+                    },
+                    models::InstructionEntry {
+                        keyword: "WORKDIR".to_string(),
+                        arguments: "/app".to_string(),
+                        stage_index: 1,
+                        line: 11,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: "--from=builder /app/node_modules ./node_modules".to_string(),
+                        stage_index: 1,
+                        line: 12,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: ". .".to_string(),
+                        stage_index: 1,
+                        line: 14,
+                    },
+                    models::InstructionEntry {
+                        keyword: "CMD".to_string(),
+                        arguments: "[\"npm\", \"start\"]".to_string(),
+                        stage_index: 1,
+                        line: 15,
+                    },
+                ],
+                copy_operations: vec![
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec!["package*.json".to_string()],
+                        destination: "./".to_string(),
+                        stage_index: 0,
+                        line: 6,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: None,
+                        checksum: None,
+                        link: false,
+                    },
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec!["/app/node_modules".to_string()],
+                        destination: "./node_modules".to_string(),
+                        stage_index: 1,
+                        line: 12,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: Some("builder".to_string()),
+                        checksum: None,
+                        link: false,
+                    },
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec![".".to_string()],
+                        destination: ".".to_string(),
+                        stage_index: 1,
+                        line: 14,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: None,
+                        checksum: None,
+                        link: false,
+                    },
+                ],
+                run_operations: vec![models::RunOperation {
+                    command: "npm ci".to_string(),
+                    network: None,
+                    shell: None,
+                    stage_index: 0,
+                    line: 7,
+                }],
+                quality_score: 100,
+                ..Default::default()
             };
 
             let res = analyze_dockerfile(dockerfile);
@@ -1216,10 +6001,12 @@ COPY --from=builder /app/dist /usr/share/nginx/html
 
             let msa = models::MultistageAnalysis {
                 is_multistage: true,
+                has_multiple_stages: true,
                 stages_used_as_base_images: vec![],
                 stages_copied_from: vec!["builder".to_string()],
                 stages_added_from: vec![],
                 unused_stages: vec!["another-unused".to_string(), "unused-stage".to_string()],
+                artifact_only_stages: vec!["builder".to_string()],
             };
             let images: Vec<models::Image> = vec![
                 models::Image {
@@ -1267,6 +6054,7 @@ COPY --from=builder /app/dist /usr/share/nginx/html
                     ("RUN".to_string(), 3),
                     ("WORKDIR".to_string(), 1),
                 ]),
+                by_type_per_stage: None,
             };
 
             let expected = models::Analysis {
@@ -1285,6 +6073,195 @@ COPY --from=builder /app/dist /usr/share/nginx/html
                 args: HashMap::new(),
                 labels: HashMap::new(),
                 env_vars: HashMap::new(),
+                escape_char: '\\',
+                final_base_chain: vec!["nginx:alpine".to_string()],
+                distinct_base_images: 4,
+                registries: vec!["docker.io".to_string()],
+                removable_stages: vec!["another-unused".to_string(), "unused-stage".to_string()],
+                stages: vec![
+                    models::StageAnalysis {
+                        index: 0,
+                        name: Some("unused-stage".to_string()),
+                        base_image: "ubuntu:20.04".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                    models::StageAnalysis {
+                        index: 1,
+                        name: Some("another-unused".to_string()),
+                        base_image: "alpine:3.18".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                    models::StageAnalysis {
+                        index: 2,
+                        name: Some("builder".to_string()),
+                        base_image: "node:18-alpine".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                    models::StageAnalysis {
+                        index: 3,
+                        name: None,
+                        base_image: "nginx:alpine".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![2],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                ],
+                flags_used: HashMap::from([("from".to_string(), 1)]),
+                file_metrics: models::FileMetrics {
+                    total_lines: 14,
+                    comment_lines: 0,
+                    blank_lines: 4,
+                    instruction_lines: 10,
+                },
+                per_stage_distinct_instruction_kinds: vec![2, 2, 4, 2],
+                ordered_instructions: vec![
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "ubuntu:20.04 AS unused-stage".to_string(),
+                        stage_index: 0,
+                        line: 2,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "apt-get update".to_string(),
+                        stage_index: 0,
+                        line: 3,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "alpine:3.18 AS another-unused".to_string(),
+                        stage_index: 1,
+                        line: 5,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "apk add --no-cache curl".to_string(),
+                        stage_index: 1,
+                        line: 6,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "node:18-alpine AS builder".to_string(),
+                        stage_index: 2,
+                        line: 8,
+                    },
+                    models::InstructionEntry {
+                        keyword: "WORKDIR".to_string(),
+                        arguments: "/app".to_string(),
+                        stage_index: 2,
+                        line: 9,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: ". .".to_string(),
+                        stage_index: 2,
+                        line: 10,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "npm run build".to_string(),
+                        stage_index: 2,
+                        line: 11,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "nginx:alpine".to_string(),
+                        stage_index: 3,
+                        line: 13,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: "--from=builder /app/dist /usr/share/nginx/html".to_string(),
+                        stage_index: 3,
+                        line: 14,
+                    },
+                ],
+                copy_operations: vec![
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec![".".to_string()],
+                        destination: ".".to_string(),
+                        stage_index: 2,
+                        line: 10,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: None,
+                        checksum: None,
+                        link: false,
+                    },
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec!["/app/dist".to_string()],
+                        destination: "/usr/share/nginx/html".to_string(),
+                        stage_index: 3,
+                        line: 14,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: Some("builder".to_string()),
+                        checksum: None,
+                        link: false,
+                    },
+                ],
+                run_operations: vec![
+                    models::RunOperation {
+                        command: "apt-get update".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 0,
+                        line: 3,
+                    },
+                    models::RunOperation {
+                        command: "apk add --no-cache curl".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 1,
+                        line: 6,
+                    },
+                    models::RunOperation {
+                        command: "npm run build".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 2,
+                        line: 11,
+                    },
+                ],
+                findings: vec![
+                    models::Finding {
+                        rule_id: "missing-cmd".to_string(),
+                        severity: "info".to_string(),
+                        message: "The final stage defines no CMD or ENTRYPOINT, so the image runs nothing by default unless the base image nginx:alpine provides one".to_string(),
+                        line: None,
+                    },
+                ],
+                quality_score: 98,
+                quality_deductions: HashMap::from([("missing-cmd".to_string(), 2)]),
+                ..Default::default()
             };
 
             let res = analyze_dockerfile(dockerfile);
@@ -1306,10 +6283,12 @@ COPY --from=builder /app/dist /usr/share/nginx/html
 
             let msa = models::MultistageAnalysis {
                 is_multistage: true,
+                has_multiple_stages: true,
                 stages_used_as_base_images: vec![],
                 stages_copied_from: vec!["builder".to_string()],
                 stages_added_from: vec![],
                 unused_stages: vec![],
+                artifact_only_stages: vec!["builder".to_string()],
             };
             let images: Vec<models::Image> = vec![
                 models::Image {
@@ -1339,6 +6318,7 @@ COPY --from=builder /app/dist /usr/share/nginx/html
                     ("RUN".to_string(), 1),
                     ("WORKDIR".to_string(), 1),
                 ]),
+                by_type_per_stage: None,
             };
 
             let expected = models::Analysis {
@@ -1353,6 +6333,130 @@ COPY --from=builder /app/dist /usr/share/nginx/html
                 args: HashMap::new(),
                 labels: HashMap::new(),
                 env_vars: HashMap::new(),
+                escape_char: '\\',
+                final_base_chain: vec!["nginx:alpine".to_string()],
+                distinct_base_images: 2,
+                registries: vec!["docker.io".to_string()],
+                stages: vec![
+                    models::StageAnalysis {
+                        index: 0,
+                        name: Some("builder".to_string()),
+                        base_image: "node:18-alpine".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![],
+                        platform: Some("linux/amd64".to_string()),
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                    models::StageAnalysis {
+                        index: 1,
+                        name: None,
+                        base_image: "nginx:alpine".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![0],
+                        platform: Some("linux/amd64".to_string()),
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                ],
+                flags_used: HashMap::from([("from".to_string(), 1), ("platform".to_string(), 2)]),
+                file_metrics: models::FileMetrics {
+                    total_lines: 8,
+                    comment_lines: 0,
+                    blank_lines: 2,
+                    instruction_lines: 6,
+                },
+                per_stage_distinct_instruction_kinds: vec![4, 2],
+                ordered_instructions: vec![
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "--platform=linux/amd64 node:18-alpine AS builder".to_string(),
+                        stage_index: 0,
+                        line: 2,
+                    },
+                    models::InstructionEntry {
+                        keyword: "WORKDIR".to_string(),
+                        arguments: "/app".to_string(),
+                        stage_index: 0,
+                        line: 3,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: ". .".to_string(),
+                        stage_index: 0,
+                        line: 4,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "npm run build".to_string(),
+                        stage_index: 0,
+                        line: 5,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "--platform=linux/amd64 nginx:alpine".to_string(),
+                        stage_index: 1,
+                        line: 7,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: "--from=builder /app/dist /usr/share/nginx/html".to_string(),
+                        stage_index: 1,
+                        line: 8,
+                    },
+                ],
+                copy_operations: vec![
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec![".".to_string()],
+                        destination: ".".to_string(),
+                        stage_index: 0,
+                        line: 4,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: None,
+                        checksum: None,
+                        link: false,
+                    },
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec!["/app/dist".to_string()],
+                        destination: "/usr/share/nginx/html".to_string(),
+                        stage_index: 1,
+                        line: 8,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: Some("builder".to_string()),
+                        checksum: None,
+                        link: false,
+                    },
+                ],
+                run_operations: vec![
+                    models::RunOperation {
+                        command: "npm run build".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 0,
+                        line: 5,
+                    },
+                ],
+                findings: vec![
+                    models::Finding {
+                        rule_id: "missing-cmd".to_string(),
+                        severity: "info".to_string(),
+                        message: "The final stage defines no CMD or ENTRYPOINT, so the image runs nothing by default unless the base image nginx:alpine provides one".to_string(),
+                        line: None,
+                    },
+                ],
+                quality_score: 98,
+                quality_deductions: HashMap::from([("missing-cmd".to_string(), 2)]),
+                ..Default::default()
             };
 
             let res = analyze_dockerfile(dockerfile);
@@ -1370,10 +6474,12 @@ CMD ["/binary"]
 
             let msa = models::MultistageAnalysis {
                 is_multistage: false,
+                has_multiple_stages: false,
                 stages_used_as_base_images: vec![],
                 stages_copied_from: vec![],
                 stages_added_from: vec![],
                 unused_stages: vec![],
+                artifact_only_stages: vec![],
             };
             let images: Vec<models::Image> = vec![models::Image {
                 full: "scratch".to_string(),
@@ -1391,6 +6497,7 @@ CMD ["/binary"]
                     ("COPY".to_string(), 1),
                     ("FROM".to_string(), 1),
                 ]),
+                by_type_per_stage: None,
             };
 
             let expected = models::Analysis {
@@ -1405,6 +6512,63 @@ CMD ["/binary"]
                 args: HashMap::new(),
                 labels: HashMap::new(),
                 env_vars: HashMap::new(),
+                escape_char: '\\',
+                final_base_chain: vec!["scratch".to_string()],
+                distinct_base_images: 1,
+                stages: vec![models::StageAnalysis {
+                    index: 0,
+                    name: None,
+                    base_image: "scratch".to_string(),
+                    args: HashMap::new(),
+                    labels: HashMap::new(),
+                    env_vars: HashMap::new(),
+                    depends_on: vec![],
+                    platform: None,
+                    user: None,
+                    exposed_ports: vec![],
+                    entrypoint: None,
+                }],
+                file_metrics: models::FileMetrics {
+                    total_lines: 4,
+                    comment_lines: 0,
+                    blank_lines: 1,
+                    instruction_lines: 3,
+                },
+                per_stage_distinct_instruction_kinds: vec![3],
+                ordered_instructions: vec![
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "scratch".to_string(),
+                        stage_index: 0,
+                        line: 2,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: "binary /".to_string(),
+                        stage_index: 0,
+                        line: 3,
+                    },
+                    models::InstructionEntry {
+                        keyword: "CMD".to_string(),
+                        arguments: "[\"/binary\"]".to_string(),
+                        stage_index: 0,
+                        line: 4,
+                    },
+                ],
+                copy_operations: vec![models::CopyOperation {
+                    instruction: "COPY".to_string(),
+                    sources: vec!["binary".to_string()],
+                    destination: "/".to_string(),
+                    stage_index: 0,
+                    line: 3,
+                    chmod: None,
+                    chmod_octal: None,
+                    from_source: None,
+                    checksum: None,
+                    link: false,
+                }],
+                quality_score: 100,
+                ..Default::default()
             };
 
             let res = analyze_dockerfile(dockerfile);
@@ -1414,30 +6578,20 @@ CMD ["/binary"]
 
         #[test]
         fn test_multistage_complex_dependency_chain() {
-            let dockerfile = r#"
-FROM alpine:3.18 AS source
-RUN echo "source data" > /data.txt
-
-FROM ubuntu:20.04 AS processor
-COPY --from=source /data.txt ./
-RUN cat data.txt > processed.txt
-
-FROM node:18-alpine AS builder
-ADD --from=processor /processed.txt ./
-COPY . .
-RUN npm run build
-
-FROM nginx:alpine
-COPY --from=builder /app/dist /usr/share/nginx/html
-ADD --from=source /data.txt /usr/share/nginx/html/
-"#;
+            let dockerfile = COMPLEX_DEPENDENCY_CHAIN_DOCKERFILE;
 
             let msa = models::MultistageAnalysis {
                 is_multistage: true,
+                has_multiple_stages: true,
                 stages_used_as_base_images: vec![],
                 stages_copied_from: vec!["builder".to_string(), "source".to_string()],
                 stages_added_from: vec!["processor".to_string(), "source".to_string()],
                 unused_stages: vec![],
+                artifact_only_stages: vec![
+                    "builder".to_string(),
+                    "processor".to_string(),
+                    "source".to_string(),
+                ],
             };
             let images: Vec<models::Image> = vec![
                 models::Image {
@@ -1485,6 +6639,7 @@ ADD --from=source /data.txt /usr/share/nginx/html/
                     ("FROM".to_string(), 4),
                     ("RUN".to_string(), 3),
                 ]),
+                by_type_per_stage: None,
             };
 
             let expected = models::Analysis {
@@ -1503,11 +6658,365 @@ ADD --from=source /data.txt /usr/share/nginx/html/
                 args: HashMap::new(),
                 labels: HashMap::new(),
                 env_vars: HashMap::new(),
+                escape_char: '\\',
+                final_base_chain: vec!["nginx:alpine".to_string()],
+                distinct_base_images: 4,
+                registries: vec!["docker.io".to_string()],
+                stages: vec![
+                    models::StageAnalysis {
+                        index: 0,
+                        name: Some("source".to_string()),
+                        base_image: "alpine:3.18".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                    models::StageAnalysis {
+                        index: 1,
+                        name: Some("processor".to_string()),
+                        base_image: "ubuntu:20.04".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![0],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                    models::StageAnalysis {
+                        index: 2,
+                        name: Some("builder".to_string()),
+                        base_image: "node:18-alpine".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![1],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                    models::StageAnalysis {
+                        index: 3,
+                        name: None,
+                        base_image: "nginx:alpine".to_string(),
+                        args: HashMap::new(),
+                        labels: HashMap::new(),
+                        env_vars: HashMap::new(),
+                        depends_on: vec![0, 2],
+                        platform: None,
+                        user: None,
+                        exposed_ports: vec![],
+                        entrypoint: None,
+                    },
+                ],
+                flags_used: HashMap::from([("from".to_string(), 4)]),
+                file_metrics: models::FileMetrics {
+                    total_lines: 16,
+                    comment_lines: 0,
+                    blank_lines: 4,
+                    instruction_lines: 12,
+                },
+                per_stage_distinct_instruction_kinds: vec![2, 3, 4, 3],
+                ordered_instructions: vec![
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "alpine:3.18 AS source".to_string(),
+                        stage_index: 0,
+                        line: 2,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "echo \"source data\" > /data.txt".to_string(),
+                        stage_index: 0,
+                        line: 3,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "ubuntu:20.04 AS processor".to_string(),
+                        stage_index: 1,
+                        line: 5,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: "--from=source /data.txt ./".to_string(),
+                        stage_index: 1,
+                        line: 6,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "cat data.txt > processed.txt".to_string(),
+                        stage_index: 1,
+                        line: 7,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "node:18-alpine AS builder".to_string(),
+                        stage_index: 2,
+                        line: 9,
+                    },
+                    models::InstructionEntry {
+                        keyword: "ADD".to_string(),
+                        arguments: "--from=processor /processed.txt ./".to_string(),
+                        stage_index: 2,
+                        line: 10,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: ". .".to_string(),
+                        stage_index: 2,
+                        line: 11,
+                    },
+                    models::InstructionEntry {
+                        keyword: "RUN".to_string(),
+                        arguments: "npm run build".to_string(),
+                        stage_index: 2,
+                        line: 12,
+                    },
+                    models::InstructionEntry {
+                        keyword: "FROM".to_string(),
+                        arguments: "nginx:alpine".to_string(),
+                        stage_index: 3,
+                        line: 14,
+                    },
+                    models::InstructionEntry {
+                        keyword: "COPY".to_string(),
+                        arguments: "--from=builder /app/dist /usr/share/nginx/html".to_string(),
+                        stage_index: 3,
+                        line: 15,
+                    },
+                    models::InstructionEntry {
+                        keyword: "ADD".to_string(),
+                        arguments: "--from=source /data.txt /usr/share/nginx/html/".to_string(),
+                        stage_index: 3,
+                        line: 16,
+                    },
+                ],
+                copy_operations: vec![
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec!["/data.txt".to_string()],
+                        destination: "./".to_string(),
+                        stage_index: 1,
+                        line: 6,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: Some("source".to_string()),
+                        checksum: None,
+                        link: false,
+                    },
+                    models::CopyOperation {
+                        instruction: "ADD".to_string(),
+                        sources: vec!["/processed.txt".to_string()],
+                        destination: "./".to_string(),
+                        stage_index: 2,
+                        line: 10,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: Some("processor".to_string()),
+                        checksum: None,
+                        link: false,
+                    },
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec![".".to_string()],
+                        destination: ".".to_string(),
+                        stage_index: 2,
+                        line: 11,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: None,
+                        checksum: None,
+                        link: false,
+                    },
+                    models::CopyOperation {
+                        instruction: "COPY".to_string(),
+                        sources: vec!["/app/dist".to_string()],
+                        destination: "/usr/share/nginx/html".to_string(),
+                        stage_index: 3,
+                        line: 15,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: Some("builder".to_string()),
+                        checksum: None,
+                        link: false,
+                    },
+                    models::CopyOperation {
+                        instruction: "ADD".to_string(),
+                        sources: vec!["/data.txt".to_string()],
+                        destination: "/usr/share/nginx/html/".to_string(),
+                        stage_index: 3,
+                        line: 16,
+                        chmod: None,
+                        chmod_octal: None,
+                        from_source: Some("source".to_string()),
+                        checksum: None,
+                        link: false,
+                    },
+                ],
+                run_operations: vec![
+                    models::RunOperation {
+                        command: "echo \"source data\" > /data.txt".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 0,
+                        line: 3,
+                    },
+                    models::RunOperation {
+                        command: "cat data.txt > processed.txt".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 1,
+                        line: 7,
+                    },
+                    models::RunOperation {
+                        command: "npm run build".to_string(),
+                        network: None,
+                        shell: None,
+                        stage_index: 2,
+                        line: 12,
+                    },
+                ],
+                findings: vec![
+                    models::Finding {
+                        rule_id: "relative-copy-destination".to_string(),
+                        severity: "warning".to_string(),
+                        message: "COPY destination './' is relative and no WORKDIR is set in the stage; it resolves to '/'".to_string(),
+                        line: Some(6),
+                    },
+                    models::Finding {
+                        rule_id: "relative-copy-destination".to_string(),
+                        severity: "warning".to_string(),
+                        message: "ADD destination './' is relative and no WORKDIR is set in the stage; it resolves to '/'".to_string(),
+                        line: Some(10),
+                    },
+                    models::Finding {
+                        rule_id: "relative-copy-destination".to_string(),
+                        severity: "warning".to_string(),
+                        message: "COPY destination '.' is relative and no WORKDIR is set in the stage; it resolves to '/'".to_string(),
+                        line: Some(11),
+                    },
+                    models::Finding {
+                        rule_id: "prefer-copy".to_string(),
+                        severity: "warning".to_string(),
+                        message: "ADD of local files to './' does nothing COPY doesn't; use COPY instead".to_string(),
+                        line: Some(10),
+                    },
+                    models::Finding {
+                        rule_id: "prefer-copy".to_string(),
+                        severity: "warning".to_string(),
+                        message: "ADD of local files to '/usr/share/nginx/html/' does nothing COPY doesn't; use COPY instead".to_string(),
+                        line: Some(16),
+                    },
+                    models::Finding {
+                        rule_id: "missing-cmd".to_string(),
+                        severity: "info".to_string(),
+                        message: "The final stage defines no CMD or ENTRYPOINT, so the image runs nothing by default unless the base image nginx:alpine provides one".to_string(),
+                        line: None,
+                    },
+                ],
+                quality_score: 48,
+                quality_deductions: HashMap::from([
+                    ("missing-cmd".to_string(), 2),
+                    ("prefer-copy".to_string(), 20),
+                    ("relative-copy-destination".to_string(), 30),
+                ]),
+                ..Default::default()
             };
 
             let res = analyze_dockerfile(dockerfile);
             assert!(res.is_ok());
             assert_eq!(res.unwrap(), expected);
         }
+
+        #[test]
+        fn test_has_multiple_stages_without_references() {
+            let dockerfile =
+                "FROM alpine AS lint\nRUN echo lint\nFROM node:22 AS app\nRUN echo app\n";
+            let msa = analyze_multistage_only(dockerfile).unwrap();
+            assert!(msa.has_multiple_stages);
+            assert!(!msa.is_multistage);
+            assert_eq!(msa.unused_stages, vec!["app", "lint"]);
+        }
+
+        #[test]
+        fn test_artifact_only_stages() {
+            let dockerfile = "FROM alpine AS base\nFROM base AS build\nRUN make\nFROM alpine\nCOPY --from=build /out /app\nCOPY --from=base /etc/ssl /etc/ssl\n";
+            let msa = analyze_multistage_only(dockerfile).unwrap();
+            assert_eq!(msa.stages_copied_from, vec!["base", "build"]);
+            assert_eq!(msa.artifact_only_stages, vec!["build"]);
+        }
+
+        #[test]
+        fn test_build_order_complex_dependency_chain() {
+            let analysis = analyze_dockerfile(COMPLEX_DEPENDENCY_CHAIN_DOCKERFILE).unwrap();
+            let depends_on: Vec<_> = analysis
+                .stages
+                .iter()
+                .map(|s| s.depends_on.clone())
+                .collect();
+            assert_eq!(depends_on, vec![vec![], vec![0], vec![1], vec![0, 2]]);
+
+            let order = analysis.stage_build_order().unwrap();
+            let position = |name: &str| {
+                let index = analysis
+                    .stages
+                    .iter()
+                    .position(|s| s.name.as_deref() == Some(name))
+                    .unwrap();
+                order.iter().position(|&i| i == index).unwrap()
+            };
+            assert!(position("source") < position("processor"));
+            assert!(position("processor") < position("builder"));
+            assert_eq!(order.last(), Some(&3));
+        }
+
+        #[test]
+        fn test_to_dot_complex_dependency_chain() {
+            let analysis = analyze_dockerfile(COMPLEX_DEPENDENCY_CHAIN_DOCKERFILE).unwrap();
+            let dot = analysis.to_dot();
+            assert!(dot.starts_with("digraph stages {\n"));
+            for line in [
+                "    stage0 [label=\"source\"];\n",
+                "    stage1 [label=\"processor\"];\n",
+                "    stage2 [label=\"builder\"];\n",
+                "    stage3 [label=\"stage 3\"];\n",
+                "    stage0 -> stage1 [label=\"copy\"];\n",
+                "    stage1 -> stage2 [label=\"add\"];\n",
+                "    stage2 -> stage3 [label=\"copy\"];\n",
+                "    stage0 -> stage3 [label=\"add\"];\n",
+            ] {
+                assert!(dot.contains(line), "missing {line:?} in {dot}");
+            }
+            assert_eq!(dot.matches("->").count(), 4);
+        }
+
+        #[test]
+        fn test_to_dot_base_edges() {
+            let dot = analyze_dockerfile("FROM alpine AS base\nFROM base\n")
+                .unwrap()
+                .to_dot();
+            assert!(dot.contains("    stage0 -> stage1 [label=\"base\"];\n"));
+        }
+
+        #[test]
+        fn test_build_order_cycle() {
+            let dockerfile = r#"
+FROM alpine AS first
+COPY --from=second /b /b
+
+FROM alpine AS second
+COPY --from=first /a /a
+"#;
+            let analysis = analyze_dockerfile(dockerfile).unwrap();
+            let err = analysis.stage_build_order().unwrap_err();
+            assert!(err.contains("[0, 1]"));
+        }
     }
 }
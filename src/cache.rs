@@ -0,0 +1,150 @@
+use crate::analyzer;
+use crate::constants;
+use crate::error::AnalyzeError;
+use crate::models::{Analysis, AnalysisOptions};
+use std::collections::VecDeque;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, PoisonError};
+
+static CACHE: Mutex<AnalysisCache> =
+    Mutex::new(AnalysisCache::new(constants::DEFAULT_CACHE_CAPACITY));
+
+struct CacheEntry {
+    hash: u64,
+    body: String,
+    options: AnalysisOptions,
+    analysis: Analysis,
+}
+
+/// A least-recently-used cache of analyses keyed on a hash of the body.
+///
+/// Entries also keep the body and options they were computed from, so a hash
+/// collision or a call with different options is treated as a miss.
+pub struct AnalysisCache {
+    capacity: usize,
+    // Ordered from least to most recently used.
+    entries: VecDeque<CacheEntry>,
+}
+
+impl AnalysisCache {
+    pub const fn new(capacity: usize) -> Self {
+        AnalysisCache {
+            capacity,
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    pub fn get(&mut self, body: &str, options: &AnalysisOptions) -> Option<Analysis> {
+        let hash = hash_body(body);
+        let index = self
+            .entries
+            .iter()
+            .position(|e| e.hash == hash && e.body == body && e.options == *options)?;
+        let entry = self.entries.remove(index)?;
+        let analysis = entry.analysis.clone();
+        self.entries.push_back(entry);
+        Some(analysis)
+    }
+
+    pub fn insert(&mut self, body: &str, options: &AnalysisOptions, analysis: Analysis) {
+        let hash = hash_body(body);
+        self.entries
+            .retain(|e| !(e.hash == hash && e.body == body && e.options == *options));
+        self.entries.push_back(CacheEntry {
+            hash,
+            body: body.to_string(),
+            options: options.clone(),
+            analysis,
+        });
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+}
+
+fn hash_body(body: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Analyzes a Dockerfile through the process-wide cache. The lock is not
+/// held while analyzing, so concurrent misses on the same body may both
+/// run the analysis.
+pub fn analyze_dockerfile_cached(
+    body: &str,
+    options: &AnalysisOptions,
+) -> Result<Analysis, AnalyzeError> {
+    if let Some(analysis) = lock().get(body, options) {
+        return Ok(analysis);
+    }
+    let analysis = analyzer::analyze_dockerfile_with_options(body, options)?;
+    lock().insert(body, options, analysis.clone());
+    Ok(analysis)
+}
+
+pub fn set_cache_capacity(capacity: usize) {
+    lock().set_capacity(capacity);
+}
+
+fn lock() -> std::sync::MutexGuard<'static, AnalysisCache> {
+    // The cache holds no invariants a panic could break, so keep using it.
+    CACHE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AnalysisCache;
+    use crate::analyzer;
+    use crate::models::AnalysisOptions;
+
+    #[test]
+    fn test_cached_analysis_matches_uncached() {
+        let body = "FROM alpine:3.20\nRUN echo cached\n";
+        let first = super::analyze_dockerfile_cached(body, &AnalysisOptions::default()).unwrap();
+        let second = super::analyze_dockerfile_cached(body, &AnalysisOptions::default()).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first, analyzer::analyze_dockerfile(body).unwrap());
+    }
+
+    #[test]
+    fn test_cache_is_bounded() {
+        let options = AnalysisOptions::default();
+        let mut cache = AnalysisCache::new(2);
+        let bodies = ["FROM a\n", "FROM b\n", "FROM c\n"];
+        for body in bodies {
+            cache.insert(body, &options, analyzer::analyze_dockerfile(body).unwrap());
+        }
+        assert_eq!(cache.entries.len(), 2);
+        assert!(cache.get("FROM a\n", &options).is_none());
+
+        // Reading "FROM b" makes "FROM c" the least recently used entry.
+        assert!(cache.get("FROM b\n", &options).is_some());
+        cache.insert(
+            "FROM d\n",
+            &options,
+            analyzer::analyze_dockerfile("FROM d\n").unwrap(),
+        );
+        assert!(cache.get("FROM c\n", &options).is_none());
+        assert!(cache.get("FROM b\n", &options).is_some());
+
+        let strict = AnalysisOptions {
+            require_healthcheck: true,
+            ..Default::default()
+        };
+        assert!(cache.get("FROM b\n", &strict).is_none());
+
+        cache.set_capacity(1);
+        assert_eq!(cache.entries.len(), 1);
+    }
+}
@@ -1,14 +1,25 @@
 pub const ADD: &str = "ADD";
 pub const ARG: &str = "ARG";
+pub const CHECKSUM: &str = "checksum";
+pub const CHMOD: &str = "chmod";
 pub const CMD: &str = "CMD";
 pub const COPY: &str = "COPY";
+pub const DOCKER_HUB: &str = "docker.io";
 pub const ENTRYPOINT: &str = "ENTRYPOINT";
 pub const ENV: &str = "ENV";
 pub const EXPOSE: &str = "EXPOSE";
 pub const FROM_UC: &str = "FROM";
 pub const FROM: &str = "from";
+pub const HEREDOC_SOURCE: &str = "<<heredoc>>";
 pub const HEALTHCHECK: &str = "HEALTHCHECK";
 pub const LABEL: &str = "LABEL";
+// Escape character used when no `# escape=` parser directive is given.
+pub const DEFAULT_ESCAPE_CHAR: char = '\\';
+pub const LINK: &str = "link";
+pub const PATH: &str = "PATH";
+pub const PLATFORM: &str = "platform";
+pub const NETWORK: &str = "network";
+pub const NETWORK_NONE: &str = "none";
 pub const MAINTAINER: &str = "MAINTAINER";
 pub const ONBUILD: &str = "ONBUILD";
 pub const RUN: &str = "RUN";
@@ -17,3 +28,98 @@ pub const STOPSIGNAL: &str = "STOPSIGNAL";
 pub const USER: &str = "USER";
 pub const VOLUME: &str = "VOLUME";
 pub const WORKDIR: &str = "WORKDIR";
+
+// OpenContainers annotations recommended on every published image.
+pub const OCI_RECOMMENDED_LABELS: [&str; 9] = [
+    "org.opencontainers.image.title",
+    "org.opencontainers.image.description",
+    "org.opencontainers.image.version",
+    "org.opencontainers.image.revision",
+    "org.opencontainers.image.source",
+    "org.opencontainers.image.authors",
+    "org.opencontainers.image.licenses",
+    "org.opencontainers.image.created",
+    "org.opencontainers.image.url",
+];
+
+// Image path fragments and tag fragments that identify Windows base images,
+// e.g. mcr.microsoft.com/windows/servercore or dotnet/sdk:8.0-nanoserver-ltsc2022.
+pub const WINDOWS_IMAGE_PATHS: [&str; 2] = [
+    "mcr.microsoft.com/windows/",
+    "mcr.microsoft.com/dotnet/framework/",
+];
+pub const WINDOWS_IMAGE_TAGS: [&str; 3] = ["nanoserver", "servercore", "windowsservercore"];
+// Shells only found in Windows images.
+pub const WINDOWS_SHELLS: [&str; 3] = ["cmd", "powershell", "pwsh"];
+
+// Largest EXPOSE range expanded into individual ports.
+pub const MAX_EXPOSE_RANGE: usize = 1024;
+
+// Instruction count above which a Dockerfile is reported as too long.
+pub const DEFAULT_MAX_INSTRUCTIONS: u32 = 60;
+
+// Image tags that are moved to new releases over time. A trailing '*' matches
+// any tag with that prefix.
+pub const DEFAULT_FLOATING_TAGS: [&str; 7] = [
+    "latest", "stable", "edge", "main", "master", "nightly", "lts",
+];
+
+pub const SCRATCH: &str = "scratch";
+
+// Commands that create user accounts.
+pub const USER_CREATION_COMMANDS: [&str; 2] = ["adduser", "useradd"];
+
+// Options of useradd and of the Debian and BusyBox adduser that take a
+// separate value, which must be skipped to find the user name.
+pub const USER_CREATION_VALUE_OPTIONS: [&str; 35] = [
+    "-b",
+    "-c",
+    "-d",
+    "-e",
+    "-f",
+    "-g",
+    "-G",
+    "-h",
+    "-k",
+    "-K",
+    "-p",
+    "-P",
+    "-R",
+    "-s",
+    "-u",
+    "-Z",
+    "--base-dir",
+    "--comment",
+    "--conf",
+    "--expiredate",
+    "--firstgid",
+    "--firstuid",
+    "--gecos",
+    "--gid",
+    "--groups",
+    "--home",
+    "--home-dir",
+    "--inactive",
+    "--ingroup",
+    "--key",
+    "--password",
+    "--prefix",
+    "--root",
+    "--shell",
+    "--uid",
+];
+
+pub const SEVERITY_ERROR: &str = "error";
+pub const SEVERITY_WARNING: &str = "warning";
+pub const SEVERITY_INFO: &str = "info";
+
+// Points deducted from a quality score of 100 for each finding of a severity.
+pub const SEVERITY_WEIGHTS: [(&str, u32); 3] = [
+    (SEVERITY_ERROR, 20),
+    (SEVERITY_WARNING, 10),
+    (SEVERITY_INFO, 2),
+];
+pub const MAX_QUALITY_SCORE: u8 = 100;
+
+// Number of analyses kept by analyze_dockerfile_cached unless reconfigured.
+pub const DEFAULT_CACHE_CAPACITY: usize = 128;
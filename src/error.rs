@@ -0,0 +1,145 @@
+use pyo3::PyErr;
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use std::error::Error;
+use std::fmt;
+
+create_exception!(
+    dockerfile_analyzer,
+    DockerfileError,
+    PyValueError,
+    "Base class for errors raised while analyzing a Dockerfile."
+);
+create_exception!(
+    dockerfile_analyzer,
+    EmptyDockerfileError,
+    DockerfileError,
+    "Raised when a Dockerfile is empty or contains only comments."
+);
+create_exception!(
+    dockerfile_analyzer,
+    DockerfileParseError,
+    DockerfileError,
+    "Raised when a Dockerfile cannot be parsed."
+);
+create_exception!(
+    dockerfile_analyzer,
+    DuplicateStageError,
+    DockerfileParseError,
+    "Raised when two stages of a Dockerfile share a name."
+);
+create_exception!(
+    dockerfile_analyzer,
+    TargetNotFoundError,
+    DockerfileError,
+    "Raised when the requested target stage does not exist."
+);
+
+/// The ways analyzing a Dockerfile can fail. Each variant maps to its own
+/// Python exception, all of which derive from `ValueError`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnalyzeError {
+    EmptyInput,
+    CommentsOnly,
+    InstructionBeforeFrom(String),
+    DuplicateStage(String),
+    ParseError(String),
+    TargetNotFound(String),
+}
+
+impl AnalyzeError {
+    /// Converts the error into the matching Python exception carrying the
+    /// given message, so callers can add context without losing the type.
+    pub fn into_py_err(self, message: String) -> PyErr {
+        match self {
+            AnalyzeError::EmptyInput | AnalyzeError::CommentsOnly => {
+                EmptyDockerfileError::new_err(message)
+            }
+            AnalyzeError::InstructionBeforeFrom(_) | AnalyzeError::ParseError(_) => {
+                DockerfileParseError::new_err(message)
+            }
+            AnalyzeError::DuplicateStage(_) => DuplicateStageError::new_err(message),
+            AnalyzeError::TargetNotFound(_) => TargetNotFoundError::new_err(message),
+        }
+    }
+}
+
+impl fmt::Display for AnalyzeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnalyzeError::EmptyInput => write!(f, "Dockerfile is empty"),
+            AnalyzeError::CommentsOnly => {
+                write!(f, "Dockerfile contains no instructions, only comments")
+            }
+            AnalyzeError::InstructionBeforeFrom(message)
+            | AnalyzeError::DuplicateStage(message)
+            | AnalyzeError::ParseError(message) => write!(f, "{message}"),
+            AnalyzeError::TargetNotFound(target) => {
+                write!(f, "Target stage '{target}' not found in the Dockerfile")
+            }
+        }
+    }
+}
+
+impl Error for AnalyzeError {}
+
+impl From<AnalyzeError> for PyErr {
+    fn from(e: AnalyzeError) -> PyErr {
+        let message = e.to_string();
+        e.into_py_err(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AnalyzeError, DockerfileParseError, DuplicateStageError, EmptyDockerfileError};
+    use crate::analyzer::analyze_dockerfile;
+    use pyo3::PyErr;
+    use pyo3::exceptions::PyValueError;
+    use pyo3::prelude::*;
+
+    #[test]
+    fn test_error_variants() {
+        assert_eq!(
+            analyze_dockerfile("").unwrap_err(),
+            AnalyzeError::EmptyInput
+        );
+        assert_eq!(
+            analyze_dockerfile("# only a comment\n").unwrap_err(),
+            AnalyzeError::CommentsOnly
+        );
+        assert!(matches!(
+            analyze_dockerfile("RUN echo hi\nFROM alpine\n").unwrap_err(),
+            AnalyzeError::InstructionBeforeFrom(_)
+        ));
+        assert!(matches!(
+            analyze_dockerfile("FROM alpine AS base\nFROM debian AS base\n").unwrap_err(),
+            AnalyzeError::DuplicateStage(_)
+        ));
+        assert!(matches!(
+            analyze_dockerfile("FROM alpine\nFOO bar\n").unwrap_err(),
+            AnalyzeError::ParseError(_)
+        ));
+    }
+
+    #[test]
+    fn test_python_exception_types() {
+        Python::initialize();
+        Python::attach(|py| {
+            let empty = PyErr::from(analyze_dockerfile("").unwrap_err());
+            assert!(empty.is_instance_of::<EmptyDockerfileError>(py));
+            assert!(empty.is_instance_of::<PyValueError>(py));
+            assert!(!empty.is_instance_of::<DockerfileParseError>(py));
+
+            let parse = PyErr::from(analyze_dockerfile("FROM alpine\nFOO bar\n").unwrap_err());
+            assert!(parse.is_instance_of::<DockerfileParseError>(py));
+            assert!(!parse.is_instance_of::<EmptyDockerfileError>(py));
+
+            let duplicate = PyErr::from(
+                analyze_dockerfile("FROM alpine AS base\nFROM debian AS base\n").unwrap_err(),
+            );
+            assert!(duplicate.is_instance_of::<DuplicateStageError>(py));
+            assert!(duplicate.is_instance_of::<DockerfileParseError>(py));
+        });
+    }
+}
@@ -1,15 +1,21 @@
-use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 mod analyzer;
+mod cache;
 mod constants;
+mod error;
+mod lints;
 mod models;
 mod parse_utils;
 
+pub use analyzer::instruction_keywords;
+pub use error::AnalyzeError;
+
 #[pyfunction]
 #[doc = "Analyzes a Dockerfile and returns detailed analysis information.
 
 Args:
     dockerfile_content (str): The content of the Dockerfile to analyze
+    options (AnalysisOptions | None): Options controlling the analysis
 
 Returns:
     Analysis: A comprehensive analysis object containing information about:
@@ -19,31 +25,250 @@ Returns:
         - Instructions statistics
         - Environment variables, labels, and arguments
         - Exposed ports
+        - Lint findings
 
 Raises:
-    ValueError: If the dockerfile content is empty or invalid
+    EmptyDockerfileError: If the dockerfile content is empty
+    DockerfileParseError: If the dockerfile content is invalid
+    DuplicateStageError: If two stages share a name
 
 Example:
     >>> analysis = analyze_dockerfile('FROM ubuntu:20.04\\nRUN echo hello')
     >>> print(analysis.num_stages)
     1
 "]
-fn analyze_dockerfile(body: &str) -> PyResult<models::Analysis> {
-    let res = analyzer::analyze_dockerfile(body);
-    match res {
-        Ok(res) => Ok(res),
-        Err(e) => Err(PyValueError::new_err(e.to_string())),
-    }
+#[pyo3(signature = (body, options=None))]
+fn analyze_dockerfile(
+    body: &str,
+    options: Option<models::AnalysisOptions>,
+) -> PyResult<models::Analysis> {
+    let res = match options {
+        Some(options) => analyzer::analyze_dockerfile_with_options(body, &options),
+        None => analyzer::analyze_dockerfile(body),
+    };
+    Ok(res?)
+}
+
+#[pyfunction]
+#[doc = "Analyzes a Dockerfile like analyze_dockerfile, reusing earlier results.
+
+Analyses are kept in a process-wide least-recently-used cache keyed on the
+Dockerfile content and options. Each call returns a copy of the cached
+Analysis, so modifying the result does not affect later calls.
+
+Args:
+    body (str): The content of the Dockerfile to analyze
+    options (AnalysisOptions | None): Options controlling the analysis
+
+Returns:
+    Analysis: The same value analyze_dockerfile would return
+
+Raises:
+    EmptyDockerfileError: If the dockerfile content is empty
+    DockerfileParseError: If the dockerfile content is invalid
+"]
+#[pyo3(signature = (body, options=None))]
+fn analyze_dockerfile_cached(
+    body: &str,
+    options: Option<models::AnalysisOptions>,
+) -> PyResult<models::Analysis> {
+    let options = options.unwrap_or_default();
+    Ok(cache::analyze_dockerfile_cached(body, &options)?)
+}
+
+#[pyfunction]
+#[doc = "Sets how many analyses analyze_dockerfile_cached keeps.
+
+The least recently used entries are dropped when the cache shrinks. A
+capacity of 0 disables caching. Defaults to 128.
+
+Args:
+    capacity (int): The maximum number of cached analyses
+"]
+fn set_analysis_cache_capacity(capacity: usize) {
+    cache::set_cache_capacity(capacity);
+}
+
+#[pyfunction]
+#[doc = "Analyzes the part of a Dockerfile built by docker build --target.
+
+Only the target stage and the stages it transitively builds on or copies
+from are analyzed. Line numbers refer to the original Dockerfile, while
+stage indices refer to the stages that remain. file_metrics counts the
+lines of the whole Dockerfile.
+
+Args:
+    body (str): The content of the Dockerfile to analyze
+    target (str): The name of the target stage
+    options (AnalysisOptions | None): Options controlling the analysis
+
+Returns:
+    Analysis: The analysis of the target stage and its dependencies
+
+Raises:
+    EmptyDockerfileError: If the dockerfile content is empty
+    DockerfileParseError: If the dockerfile content is invalid
+    TargetNotFoundError: If the Dockerfile has no stage named target
+"]
+#[pyo3(signature = (body, target, options=None))]
+fn analyze_dockerfile_target(
+    body: &str,
+    target: &str,
+    options: Option<models::AnalysisOptions>,
+) -> PyResult<models::Analysis> {
+    let options = options.unwrap_or_default();
+    Ok(analyzer::analyze_dockerfile_target(body, target, &options)?)
+}
+
+#[pyfunction]
+#[doc = "Analyzes a Dockerfile given as bytes of unknown encoding.
+
+UTF-8 content is analyzed as is, with any byte order mark removed. Other
+content is decoded with invalid bytes replaced by U+FFFD, and an
+invalid-utf8 warning finding gives the line of the first one.
+
+Args:
+    data (bytes): The raw content of the Dockerfile to analyze
+    options (AnalysisOptions | None): Options controlling the analysis
+
+Returns:
+    Analysis: The analysis of the decoded Dockerfile
+
+Raises:
+    EmptyDockerfileError: If the dockerfile content is empty
+    DockerfileParseError: If the dockerfile content is invalid
+"]
+#[pyo3(signature = (data, options=None))]
+fn analyze_dockerfile_bytes(
+    data: &[u8],
+    options: Option<models::AnalysisOptions>,
+) -> PyResult<models::Analysis> {
+    let options = options.unwrap_or_default();
+    Ok(analyzer::analyze_dockerfile_bytes(data, &options)?)
+}
+
+#[pyfunction]
+#[doc = "Analyzes a list of Dockerfiles.
+
+The GIL is released while the Dockerfiles are analyzed, and with
+parallel=True they are analyzed across multiple threads.
+
+Args:
+    bodies (list[str]): The contents of the Dockerfiles to analyze
+    options (AnalysisOptions | None): Options applied to every Dockerfile
+    parallel (bool): Whether to analyze the Dockerfiles in parallel
+
+Returns:
+    list[Analysis]: One analysis per Dockerfile, in input order
+
+Raises:
+    DockerfileError: If any Dockerfile is empty or invalid; the exception
+        has the subclass matching the failure and the message gives the
+        index of the first one that failed
+"]
+#[pyo3(signature = (bodies, options=None, parallel=false))]
+fn analyze_dockerfiles(
+    py: Python,
+    bodies: Vec<String>,
+    options: Option<models::AnalysisOptions>,
+    parallel: bool,
+) -> PyResult<Vec<models::Analysis>> {
+    let options = options.unwrap_or_default();
+    let results = py.detach(|| analyzer::analyze_dockerfiles(&bodies, &options, parallel));
+    results
+        .into_iter()
+        .enumerate()
+        .map(|(index, res)| {
+            res.map_err(|e| {
+                let message = format!("Dockerfile at index {index}: {e}");
+                e.into_py_err(message)
+            })
+        })
+        .collect()
+}
+
+#[pyfunction]
+#[doc = "Analyzes only the multistage structure of a Dockerfile.
+
+This is a faster alternative to analyze_dockerfile when only stage
+relationships are needed; instructions, environment variables, labels,
+ports and lint findings are not extracted.
+
+Args:
+    body (str): The content of the Dockerfile to analyze
+
+Returns:
+    MultistageAnalysis: The same value as Analysis.multistage_analysis
+
+Raises:
+    EmptyDockerfileError: If the dockerfile content is empty
+    DockerfileParseError: If the dockerfile content is invalid
+"]
+fn analyze_multistage_only(body: &str) -> PyResult<models::MultistageAnalysis> {
+    Ok(analyzer::analyze_multistage_only(body)?)
+}
+
+#[pyfunction]
+#[doc = "Rebuilds an Analysis from the JSON produced by Analysis.to_json.
+
+Args:
+    json (str): A JSON string previously returned by Analysis.to_json
+
+Returns:
+    Analysis: An analysis equal to the one that was serialized
+
+Raises:
+    ValueError: If the JSON is malformed or does not describe an Analysis
+"]
+fn analysis_from_json(json: &str) -> PyResult<models::Analysis> {
+    models::Analysis::from_json(json)
 }
 
 /// A Python module implemented in Rust.
 #[pymodule]
 fn dockerfile_analyzer(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(analyze_dockerfile, m)?)?;
+    m.add_function(wrap_pyfunction!(analyze_dockerfile_cached, m)?)?;
+    m.add_function(wrap_pyfunction!(set_analysis_cache_capacity, m)?)?;
+    m.add_function(wrap_pyfunction!(analyze_dockerfile_target, m)?)?;
+    m.add_function(wrap_pyfunction!(analyze_dockerfile_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(analyze_dockerfiles, m)?)?;
+    m.add_function(wrap_pyfunction!(analyze_multistage_only, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis_from_json, m)?)?;
+    m.add(
+        "DockerfileError",
+        m.py().get_type::<error::DockerfileError>(),
+    )?;
+    m.add(
+        "EmptyDockerfileError",
+        m.py().get_type::<error::EmptyDockerfileError>(),
+    )?;
+    m.add(
+        "DockerfileParseError",
+        m.py().get_type::<error::DockerfileParseError>(),
+    )?;
+    m.add(
+        "DuplicateStageError",
+        m.py().get_type::<error::DuplicateStageError>(),
+    )?;
+    m.add(
+        "TargetNotFoundError",
+        m.py().get_type::<error::TargetNotFoundError>(),
+    )?;
     m.add_class::<models::Analysis>()?;
+    m.add_class::<models::AnalysisOptions>()?;
     m.add_class::<models::MultistageAnalysis>()?;
+    m.add_class::<models::StageAnalysis>()?;
+    m.add_class::<models::CopyOperation>()?;
+    m.add_class::<models::ExposedPort>()?;
+    m.add_class::<models::FileMetrics>()?;
+    m.add_class::<models::Finding>()?;
     m.add_class::<models::Image>()?;
     m.add_class::<models::ImageComponents>()?;
+    m.add_class::<models::InstructionEntry>()?;
     m.add_class::<models::InstructionStats>()?;
+    m.add_class::<models::OciLabelReport>()?;
+    m.add_class::<models::RunOperation>()?;
+    m.add_class::<models::Summary>()?;
     Ok(())
 }
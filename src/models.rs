@@ -1,28 +1,311 @@
+use crate::constants;
 use pyo3::prelude::*;
-use pyo3::types::PyDict;
-use serde::Serialize;
-use std::collections::HashMap;
+use pyo3::types::{PyBytes, PyDict, PyType};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+
+fn hash_value<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[pyclass(from_py_object)]
+#[doc = "A single instruction, in the order it appears in the Dockerfile.
+
+Global ARG instructions declared before the first FROM are reported with
+a stage_index of 0.
+
+Attributes:
+    keyword (str): The instruction keyword in uppercase (e.g., 'RUN')
+    arguments (str): The raw text following the keyword
+    stage_index (int): Zero-based index of the stage containing the instruction
+    line (int): One-based line number on which the instruction starts
+"]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct InstructionEntry {
+    #[pyo3(get)]
+    pub keyword: String,
+    #[pyo3(get)]
+    pub arguments: String,
+    #[pyo3(get)]
+    pub stage_index: usize,
+    #[pyo3(get)]
+    pub line: usize,
+}
+
+#[pymethods]
+impl InstructionEntry {
+    fn __repr__(&self) -> String {
+        format!(
+            "InstructionEntry(keyword={:?}, arguments={:?}, stage_index={}, line={})",
+            self.keyword, self.arguments, self.stage_index, self.line
+        )
+    }
+
+    fn to_dict(&self, py: Python) -> PyResult<Py<PyAny>> {
+        let dict = PyDict::new(py);
+        dict.set_item("keyword", &self.keyword)?;
+        dict.set_item("arguments", &self.arguments)?;
+        dict.set_item("stage_index", self.stage_index)?;
+        dict.set_item("line", self.line)?;
+        Ok(dict.into())
+    }
+}
 
 #[pyclass(from_py_object)]
+#[doc = "Options controlling how a Dockerfile is analyzed.
+
+Attributes:
+    exclude_stage_references_from_images (bool): Omit stage names used as
+        base images (e.g., 'FROM base') from the images list. Defaults to False.
+    require_healthcheck (bool): Report a finding when the final stage exposes
+        a port but has no HEALTHCHECK. Off by default since it only applies
+        to service images. Defaults to False.
+    instruction_counts_per_stage (bool): Fill in
+        InstructionStats.by_type_per_stage. Defaults to False.
+    require_tag_with_digest (bool): Report a warning for FROM images pinned
+        by digest without a tag, for policies that want a readable version
+        next to every digest. Replaces the digest-needs-tag-comment finding,
+        which a version comment satisfies. Defaults to False.
+    max_instructions (int): Report an info finding when the Dockerfile has
+        more instructions than this. Defaults to 60.
+    run_lints (bool): Run the lint rules and the RUN command extraction they
+        rely on. When False, findings, run_operations, created_users and
+        copy_optimization_suggestions are left empty, has_offline_runs is
+        False and quality_score is 100. On a typical multistage Dockerfile
+        this makes fewer than half the allocations (606 versus 1603) and
+        cuts analysis time by more than half. Defaults to True.
+    floating_tags (list[str]): Base image tags reported as moving targets,
+        compared case-insensitively. A trailing '*' matches any tag with
+        that prefix. Defaults to latest, stable, edge, main, master, nightly
+        and lts.
+    validate_oci_labels (bool): Report a warning for OCI labels whose value
+        does not have the expected type: an RFC 3339 timestamp for
+        org.opencontainers.image.created and a URL for its url, source and
+        documentation labels. Defaults to False.
+    require_explicit_registry (bool): Report FROM and COPY/ADD --from images
+        that name no registry and so are pulled from Docker Hub, unless
+        docker.io is in allowed_registries. Defaults to False.
+    allowed_registries (list[str]): With require_explicit_registry, also
+        report images from registries not in this list, ignoring case. An
+        empty list allows any explicit registry. Defaults to [].
+    honor_check_directive (bool): Suppress the findings whose rule matches
+        a BuildKit check skipped by a '# check=skip=' parser directive, for
+        example prefer-exec-form for JSONArgsRecommended. 'skip=all'
+        suppresses every rule with a BuildKit equivalent. Defaults to True.
+    preserve_stage_case (bool): Report stage_names with the casing used in
+        the AS clause instead of lowercased. Stage references are still
+        matched case-insensitively and reported lowercased elsewhere.
+        Defaults to False.
+    max_run_length (int | None): Report an info finding for a RUN whose
+        command is longer than this many characters, suggesting it be split
+        or use a cache mount. Defaults to None, which disables the check.
+    max_run_commands (int | None): Report an info finding for a RUN that
+        chains more than this many commands. Defaults to None, which
+        disables the check.
+"]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct AnalysisOptions {
+    #[pyo3(get, set)]
+    pub exclude_stage_references_from_images: bool,
+    #[pyo3(get, set)]
+    pub require_healthcheck: bool,
+    #[pyo3(get, set)]
+    pub instruction_counts_per_stage: bool,
+    #[pyo3(get, set)]
+    pub require_tag_with_digest: bool,
+    #[pyo3(get, set)]
+    pub max_instructions: u32,
+    #[pyo3(get, set)]
+    pub run_lints: bool,
+    #[pyo3(get, set)]
+    pub floating_tags: Vec<String>,
+    #[pyo3(get, set)]
+    pub validate_oci_labels: bool,
+    #[pyo3(get, set)]
+    pub require_explicit_registry: bool,
+    #[pyo3(get, set)]
+    pub allowed_registries: Vec<String>,
+    #[pyo3(get, set)]
+    pub honor_check_directive: bool,
+    #[pyo3(get, set)]
+    pub preserve_stage_case: bool,
+    #[pyo3(get, set)]
+    pub max_run_length: Option<u32>,
+    #[pyo3(get, set)]
+    pub max_run_commands: Option<u32>,
+}
+
+fn default_floating_tags() -> Vec<String> {
+    constants::DEFAULT_FLOATING_TAGS
+        .iter()
+        .map(|tag| tag.to_string())
+        .collect()
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        AnalysisOptions {
+            exclude_stage_references_from_images: false,
+            require_healthcheck: false,
+            instruction_counts_per_stage: false,
+            require_tag_with_digest: false,
+            max_instructions: constants::DEFAULT_MAX_INSTRUCTIONS,
+            run_lints: true,
+            floating_tags: default_floating_tags(),
+            validate_oci_labels: false,
+            require_explicit_registry: false,
+            allowed_registries: vec![],
+            honor_check_directive: true,
+            preserve_stage_case: false,
+            max_run_length: None,
+            max_run_commands: None,
+        }
+    }
+}
+
+#[pymethods]
+impl AnalysisOptions {
+    #[new]
+    #[pyo3(signature = (
+        exclude_stage_references_from_images=false,
+        require_healthcheck=false,
+        instruction_counts_per_stage=false,
+        require_tag_with_digest=false,
+        max_instructions=constants::DEFAULT_MAX_INSTRUCTIONS,
+        run_lints=true,
+        floating_tags=None,
+        validate_oci_labels=false,
+        require_explicit_registry=false,
+        allowed_registries=vec![],
+        honor_check_directive=true,
+        preserve_stage_case=false,
+        max_run_length=None,
+        max_run_commands=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        exclude_stage_references_from_images: bool,
+        require_healthcheck: bool,
+        instruction_counts_per_stage: bool,
+        require_tag_with_digest: bool,
+        max_instructions: u32,
+        run_lints: bool,
+        floating_tags: Option<Vec<String>>,
+        validate_oci_labels: bool,
+        require_explicit_registry: bool,
+        allowed_registries: Vec<String>,
+        honor_check_directive: bool,
+        preserve_stage_case: bool,
+        max_run_length: Option<u32>,
+        max_run_commands: Option<u32>,
+    ) -> Self {
+        AnalysisOptions {
+            exclude_stage_references_from_images,
+            require_healthcheck,
+            instruction_counts_per_stage,
+            require_tag_with_digest,
+            max_instructions,
+            run_lints,
+            floating_tags: floating_tags.unwrap_or_else(default_floating_tags),
+            validate_oci_labels,
+            require_explicit_registry,
+            allowed_registries,
+            honor_check_directive,
+            preserve_stage_case,
+            max_run_length,
+            max_run_commands,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "AnalysisOptions(exclude_stage_references_from_images={}, require_healthcheck={}, instruction_counts_per_stage={}, require_tag_with_digest={}, max_instructions={}, run_lints={}, floating_tags={:?}, validate_oci_labels={}, require_explicit_registry={}, allowed_registries={:?}, honor_check_directive={}, preserve_stage_case={}, max_run_length={:?}, max_run_commands={:?})",
+            self.exclude_stage_references_from_images,
+            self.require_healthcheck,
+            self.instruction_counts_per_stage,
+            self.require_tag_with_digest,
+            self.max_instructions,
+            self.run_lints,
+            self.floating_tags,
+            self.validate_oci_labels,
+            self.require_explicit_registry,
+            self.allowed_registries,
+            self.honor_check_directive,
+            self.preserve_stage_case,
+            self.max_run_length,
+            self.max_run_commands
+        )
+    }
+
+    fn to_dict(&self, py: Python) -> PyResult<Py<PyAny>> {
+        let dict = PyDict::new(py);
+        dict.set_item(
+            "exclude_stage_references_from_images",
+            self.exclude_stage_references_from_images,
+        )?;
+        dict.set_item("require_healthcheck", self.require_healthcheck)?;
+        dict.set_item(
+            "instruction_counts_per_stage",
+            self.instruction_counts_per_stage,
+        )?;
+        dict.set_item("require_tag_with_digest", self.require_tag_with_digest)?;
+        dict.set_item("max_instructions", self.max_instructions)?;
+        dict.set_item("run_lints", self.run_lints)?;
+        dict.set_item("floating_tags", &self.floating_tags)?;
+        dict.set_item("validate_oci_labels", self.validate_oci_labels)?;
+        dict.set_item("require_explicit_registry", self.require_explicit_registry)?;
+        dict.set_item("allowed_registries", &self.allowed_registries)?;
+        dict.set_item("honor_check_directive", self.honor_check_directive)?;
+        dict.set_item("preserve_stage_case", self.preserve_stage_case)?;
+        dict.set_item("max_run_length", self.max_run_length)?;
+        dict.set_item("max_run_commands", self.max_run_commands)?;
+        Ok(dict.into())
+    }
+}
+
+#[pyclass(from_py_object, eq)]
 #[doc = "Instructions and their counts.
 
 This class contains all instructions found in the Dockerfile along with their 
 counts. It also incudes the total count.
+
+by_type_per_stage holds the counts for each stage, FROM included, in stage
+order. It is only computed when AnalysisOptions.instruction_counts_per_stage
+is set and is None otherwise. Global ARGs before the first FROM are only
+counted in by_type.
 "]
-#[derive(Debug, PartialEq, Clone, Serialize)]
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
 pub struct InstructionStats {
     #[pyo3(get)]
     pub total_count: u32,
     #[pyo3(get)]
     pub by_type: HashMap<String, u32>,
+    #[pyo3(get)]
+    pub by_type_per_stage: Option<Vec<HashMap<String, u32>>>,
 }
 
 #[pymethods]
 impl InstructionStats {
+    fn __hash__(&self) -> u64 {
+        let by_type: BTreeMap<&String, &u32> = self.by_type.iter().collect();
+        let by_type_per_stage: Option<Vec<BTreeMap<&String, &u32>>> =
+            self.by_type_per_stage.as_ref().map(|stages| {
+                stages
+                    .iter()
+                    .map(|counts| counts.iter().collect())
+                    .collect()
+            });
+        hash_value(&(self.total_count, by_type, by_type_per_stage))
+    }
+
     fn __repr__(&self) -> String {
         format!(
-            "InstructionStats(total_count={}, by_type={:?})",
-            self.total_count, self.by_type
+            "InstructionStats(total_count={}, by_type={:?}, by_type_per_stage={:?})",
+            self.total_count, self.by_type, self.by_type_per_stage
         )
     }
 
@@ -30,11 +313,60 @@ impl InstructionStats {
         let dict = PyDict::new(py);
         dict.set_item("total_count", self.total_count)?;
         dict.set_item("by_type", &self.by_type)?;
+        dict.set_item("by_type_per_stage", &self.by_type_per_stage)?;
         Ok(dict.into())
     }
 }
 
-#[pyclass(from_py_object)]
+#[pyclass(from_py_object, eq)]
+#[doc = "Line counts of a Dockerfile.
+
+Lines are classified by their content alone: parser directives count as
+comments, and continuation lines and here-document bodies count as
+instruction lines.
+
+Attributes:
+    total_lines (int): Number of lines in the file
+    comment_lines (int): Lines whose first non-blank character is '#'
+    blank_lines (int): Lines containing only whitespace
+    instruction_lines (int): All other lines
+"]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub struct FileMetrics {
+    #[pyo3(get)]
+    pub total_lines: usize,
+    #[pyo3(get)]
+    pub comment_lines: usize,
+    #[pyo3(get)]
+    pub blank_lines: usize,
+    #[pyo3(get)]
+    pub instruction_lines: usize,
+}
+
+#[pymethods]
+impl FileMetrics {
+    fn __hash__(&self) -> u64 {
+        hash_value(self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "FileMetrics(total_lines={}, comment_lines={}, blank_lines={}, instruction_lines={})",
+            self.total_lines, self.comment_lines, self.blank_lines, self.instruction_lines
+        )
+    }
+
+    fn to_dict(&self, py: Python) -> PyResult<Py<PyAny>> {
+        let dict = PyDict::new(py);
+        dict.set_item("total_lines", self.total_lines)?;
+        dict.set_item("comment_lines", self.comment_lines)?;
+        dict.set_item("blank_lines", self.blank_lines)?;
+        dict.set_item("instruction_lines", self.instruction_lines)?;
+        Ok(dict.into())
+    }
+}
+
+#[pyclass(from_py_object, eq)]
 #[doc = "Parsed components of a Docker image reference.
 
 Attributes:
@@ -43,7 +375,7 @@ Attributes:
     tag (str | None): The image tag (e.g., '20.04')
     digest (str | None): The image digest if specified
 "]
-#[derive(Debug, PartialEq, Clone, Serialize)]
+#[derive(Debug, PartialEq, Clone, Hash, Serialize, Deserialize)]
 pub struct ImageComponents {
     #[pyo3(get)]
     pub registry: Option<String>,
@@ -57,6 +389,23 @@ pub struct ImageComponents {
 
 #[pymethods]
 impl ImageComponents {
+    fn __hash__(&self) -> u64 {
+        hash_value(self)
+    }
+
+    /// Whether the reference includes a digest, with or without a tag.
+    pub fn is_digest_pinned(&self) -> bool {
+        self.digest.is_some()
+    }
+
+    /// Returns the algorithm part of the digest, e.g. 'sha256'.
+    pub fn digest_algorithm(&self) -> Option<&str> {
+        self.digest
+            .as_deref()
+            .and_then(|digest| digest.split_once(':'))
+            .map(|(algorithm, _)| algorithm)
+    }
+
     fn __repr__(&self) -> String {
         format!(
             "ImageComponents(registry={:?}, name={:?}, tag={:?}, digest={:?})",
@@ -74,14 +423,14 @@ impl ImageComponents {
     }
 }
 
-#[pyclass(from_py_object)]
+#[pyclass(from_py_object, eq)]
 #[doc = "Information about a Docker image used in a Dockerfile.
 
 Attributes:
     full (str): The complete image reference as it appears in the Dockerfile
     components (ImageComponents | None): Parsed components of the image reference
 "]
-#[derive(Debug, PartialEq, Clone, Serialize)]
+#[derive(Debug, PartialEq, Clone, Hash, Serialize, Deserialize)]
 pub struct Image {
     #[pyo3(get)]
     pub full: String,
@@ -91,6 +440,17 @@ pub struct Image {
 
 #[pymethods]
 impl Image {
+    fn __hash__(&self) -> u64 {
+        hash_value(self)
+    }
+
+    /// Whether the reference includes a digest, with or without a tag.
+    pub fn is_digest_pinned(&self) -> bool {
+        self.components
+            .as_ref()
+            .is_some_and(ImageComponents::is_digest_pinned)
+    }
+
     fn __repr__(&self) -> String {
         format!(
             "Image(full={:?}, components={:?})",
@@ -115,17 +475,61 @@ impl Image {
     }
 }
 
-#[pyclass(from_py_object)]
+#[pyclass(from_py_object, eq)]
+#[doc = "A single port declared by an EXPOSE instruction.
+
+Port ranges such as '3000-3005' are expanded into one entry per port.
+
+Attributes:
+    port (int): The port number
+    protocol (str): The protocol, 'tcp' unless given explicitly
+"]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Serialize, Deserialize)]
+pub struct ExposedPort {
+    #[pyo3(get)]
+    pub port: u16,
+    #[pyo3(get)]
+    pub protocol: String,
+}
+
+#[pymethods]
+impl ExposedPort {
+    fn __hash__(&self) -> u64 {
+        hash_value(self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ExposedPort(port={}, protocol={:?})",
+            self.port, self.protocol
+        )
+    }
+
+    fn to_dict(&self, py: Python) -> PyResult<Py<PyAny>> {
+        let dict = PyDict::new(py);
+        dict.set_item("port", self.port)?;
+        dict.set_item("protocol", &self.protocol)?;
+        Ok(dict.into())
+    }
+}
+
+#[pyclass(from_py_object, eq)]
 #[doc = "Information about multistage characteristics.
 
 This class contains an is_multistage bool along with information
-about specific stages in the Dockerfile.
+about specific stages in the Dockerfile. is_multistage is only true when a
+stage builds on or copies from another stage, while has_multiple_stages is
+true whenever there are two or more FROM instructions. artifact_only_stages
+lists the stages that other stages COPY or ADD from but never build FROM,
+such as pure builder stages.
 "]
-#[derive(Debug, PartialEq, Clone, Serialize)]
+#[derive(Debug, Default, PartialEq, Clone, Hash, Serialize, Deserialize)]
 pub struct MultistageAnalysis {
     #[pyo3(get)]
     pub is_multistage: bool,
     #[pyo3(get)]
+    pub has_multiple_stages: bool,
+    #[pyo3(get)]
     pub stages_used_as_base_images: Vec<String>,
     #[pyo3(get)]
     pub stages_copied_from: Vec<String>,
@@ -133,24 +537,33 @@ pub struct MultistageAnalysis {
     pub stages_added_from: Vec<String>,
     #[pyo3(get)]
     pub unused_stages: Vec<String>,
+    #[pyo3(get)]
+    pub artifact_only_stages: Vec<String>,
 }
 
 #[pymethods]
 impl MultistageAnalysis {
+    fn __hash__(&self) -> u64 {
+        hash_value(self)
+    }
+
     fn __repr__(&self) -> String {
         format!(
-            "MultistageAnalysis(is_multistage={}, stages_used_as_base_images={:?}, stages_copied_from={:?}, stages_added_from={:?}, unused_stages={:?})",
+            "MultistageAnalysis(is_multistage={}, has_multiple_stages={}, stages_used_as_base_images={:?}, stages_copied_from={:?}, stages_added_from={:?}, unused_stages={:?}, artifact_only_stages={:?})",
             self.is_multistage,
+            self.has_multiple_stages,
             self.stages_used_as_base_images,
             self.stages_copied_from,
             self.stages_added_from,
-            self.unused_stages
+            self.unused_stages,
+            self.artifact_only_stages
         )
     }
 
     fn to_dict(&self, py: Python) -> PyResult<Py<PyAny>> {
         let dict = PyDict::new(py);
         dict.set_item("is_multistage", self.is_multistage)?;
+        dict.set_item("has_multiple_stages", self.has_multiple_stages)?;
         dict.set_item(
             "stages_used_as_base_images",
             &self.stages_used_as_base_images,
@@ -158,89 +571,901 @@ impl MultistageAnalysis {
         dict.set_item("stages_copied_from", &self.stages_copied_from)?;
         dict.set_item("stages_added_from", &self.stages_added_from)?;
         dict.set_item("unused_stages", &self.unused_stages)?;
+        dict.set_item("artifact_only_stages", &self.artifact_only_stages)?;
+        Ok(dict.into())
+    }
+}
+
+#[pyclass(from_py_object)]
+#[doc = "Per-stage breakdown of a Dockerfile build stage.
+
+Attributes:
+    index (int): Zero-based index of the stage
+    name (str | None): The stage name given with AS, if any
+    base_image (str): The image or stage named in the FROM instruction
+    args (dict[str, str | None]): ARGs declared inside the stage
+    labels (dict[str, str]): LABELs set in the stage
+    env_vars (dict[str, str]): ENV variables set in the stage
+    depends_on (list[int]): Indices of the stages this stage builds on or
+        copies from
+    platform (str | None): The FROM --platform value, if given
+    user (str | None): The last USER set in the stage, if any
+    exposed_ports (list[str]): Ports exposed by EXPOSE instructions in the stage
+    entrypoint (str | None): The arguments of the last ENTRYPOINT in the stage, if any
+
+Only instructions written in the stage itself are reported; values inherited
+from a base stage are not. Of these, only the final stage's affect the image.
+"]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct StageAnalysis {
+    #[pyo3(get)]
+    pub index: usize,
+    #[pyo3(get)]
+    pub name: Option<String>,
+    #[pyo3(get)]
+    pub base_image: String,
+    #[pyo3(get)]
+    pub args: HashMap<String, Option<String>>,
+    #[pyo3(get)]
+    pub labels: HashMap<String, String>,
+    #[pyo3(get)]
+    pub env_vars: HashMap<String, String>,
+    #[pyo3(get)]
+    pub depends_on: Vec<usize>,
+    #[pyo3(get)]
+    pub platform: Option<String>,
+    #[pyo3(get)]
+    pub user: Option<String>,
+    #[pyo3(get)]
+    pub exposed_ports: Vec<String>,
+    #[pyo3(get)]
+    pub entrypoint: Option<String>,
+}
+
+#[pymethods]
+impl StageAnalysis {
+    fn __repr__(&self) -> String {
+        format!(
+            "StageAnalysis(index={}, name={:?}, base_image={:?}, args={:?}, labels={:?}, env_vars={:?}, depends_on={:?}, platform={:?}, user={:?}, exposed_ports={:?}, entrypoint={:?})",
+            self.index,
+            self.name,
+            self.base_image,
+            self.args,
+            self.labels,
+            self.env_vars,
+            self.depends_on,
+            self.platform,
+            self.user,
+            self.exposed_ports,
+            self.entrypoint
+        )
+    }
+
+    fn to_dict(&self, py: Python) -> PyResult<Py<PyAny>> {
+        let dict = PyDict::new(py);
+        dict.set_item("index", self.index)?;
+        dict.set_item("name", &self.name)?;
+        dict.set_item("base_image", &self.base_image)?;
+        dict.set_item("args", &self.args)?;
+        dict.set_item("labels", &self.labels)?;
+        dict.set_item("env_vars", &self.env_vars)?;
+        dict.set_item("depends_on", &self.depends_on)?;
+        dict.set_item("platform", &self.platform)?;
+        dict.set_item("user", &self.user)?;
+        dict.set_item("exposed_ports", &self.exposed_ports)?;
+        dict.set_item("entrypoint", &self.entrypoint)?;
+        Ok(dict.into())
+    }
+}
+
+#[pyclass(from_py_object)]
+#[doc = "A file copy performed by a COPY or ADD instruction.
+
+Here-document sources are reported as '<<heredoc>>'.
+
+Attributes:
+    instruction (str): Either 'COPY' or 'ADD'
+    sources (list[str]): The source paths or URLs
+    destination (str): The destination path
+    stage_index (int): Zero-based index of the stage containing the instruction
+    line (int): One-based line number on which the instruction starts
+    chmod (str | None): The --chmod value as written, if given
+    chmod_octal (int | None): The --chmod value as an octal mode, when it can
+        be resolved without knowing the source file's mode
+    from_source (str | None): The --from value, a stage name or an image
+    checksum (str | None): The ADD --checksum value, e.g. 'sha256:...'
+    link (bool): Whether --link was given
+"]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CopyOperation {
+    #[pyo3(get)]
+    pub instruction: String,
+    #[pyo3(get)]
+    pub sources: Vec<String>,
+    #[pyo3(get)]
+    pub destination: String,
+    #[pyo3(get)]
+    pub stage_index: usize,
+    #[pyo3(get)]
+    pub line: usize,
+    #[pyo3(get)]
+    pub chmod: Option<String>,
+    #[pyo3(get)]
+    pub chmod_octal: Option<u32>,
+    #[pyo3(get)]
+    pub from_source: Option<String>,
+    #[pyo3(get)]
+    pub checksum: Option<String>,
+    #[pyo3(get)]
+    pub link: bool,
+}
+
+#[pymethods]
+impl CopyOperation {
+    fn __repr__(&self) -> String {
+        format!(
+            "CopyOperation(instruction={:?}, sources={:?}, destination={:?}, stage_index={}, line={}, chmod={:?}, chmod_octal={:?}, from_source={:?}, checksum={:?}, link={})",
+            self.instruction,
+            self.sources,
+            self.destination,
+            self.stage_index,
+            self.line,
+            self.chmod,
+            self.chmod_octal,
+            self.from_source,
+            self.checksum,
+            self.link
+        )
+    }
+
+    fn to_dict(&self, py: Python) -> PyResult<Py<PyAny>> {
+        let dict = PyDict::new(py);
+        dict.set_item("instruction", &self.instruction)?;
+        dict.set_item("sources", &self.sources)?;
+        dict.set_item("destination", &self.destination)?;
+        dict.set_item("stage_index", self.stage_index)?;
+        dict.set_item("line", self.line)?;
+        dict.set_item("chmod", &self.chmod)?;
+        dict.set_item("chmod_octal", self.chmod_octal)?;
+        dict.set_item("from_source", &self.from_source)?;
+        dict.set_item("checksum", &self.checksum)?;
+        dict.set_item("link", self.link)?;
+        Ok(dict.into())
+    }
+}
+
+#[pyclass(from_py_object)]
+#[doc = "A command executed by a RUN instruction.
+
+Attributes:
+    command (str): The command, with exec-form arguments joined by spaces
+    network (str | None): The --network value ('default', 'none' or 'host'), if given
+    shell (list[str] | None): The SHELL in effect in the stage, if one was set
+    stage_index (int): Zero-based index of the stage containing the instruction
+    line (int): One-based line number on which the instruction starts
+"]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RunOperation {
+    #[pyo3(get)]
+    pub command: String,
+    #[pyo3(get)]
+    pub network: Option<String>,
+    #[pyo3(get)]
+    pub shell: Option<Vec<String>>,
+    #[pyo3(get)]
+    pub stage_index: usize,
+    #[pyo3(get)]
+    pub line: usize,
+}
+
+#[pymethods]
+impl RunOperation {
+    fn __repr__(&self) -> String {
+        format!(
+            "RunOperation(command={:?}, network={:?}, shell={:?}, stage_index={}, line={})",
+            self.command, self.network, self.shell, self.stage_index, self.line
+        )
+    }
+
+    fn to_dict(&self, py: Python) -> PyResult<Py<PyAny>> {
+        let dict = PyDict::new(py);
+        dict.set_item("command", &self.command)?;
+        dict.set_item("network", &self.network)?;
+        dict.set_item("shell", &self.shell)?;
+        dict.set_item("stage_index", self.stage_index)?;
+        dict.set_item("line", self.line)?;
+        Ok(dict.into())
+    }
+}
+
+#[pyclass(from_py_object)]
+#[doc = "A lint finding reported while analyzing a Dockerfile.
+
+Attributes:
+    rule_id (str): Identifier of the lint rule (e.g., 'digest-needs-tag-comment')
+    severity (str): One of 'error', 'warning', or 'info'
+    message (str): Human-readable description of the finding
+    line (int | None): One-based line number of the offending instruction
+"]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    #[pyo3(get)]
+    pub rule_id: String,
+    #[pyo3(get)]
+    pub severity: String,
+    #[pyo3(get)]
+    pub message: String,
+    #[pyo3(get)]
+    pub line: Option<usize>,
+}
+
+#[pymethods]
+impl Finding {
+    fn __repr__(&self) -> String {
+        format!(
+            "Finding(rule_id={:?}, severity={:?}, message={:?}, line={:?})",
+            self.rule_id, self.severity, self.message, self.line
+        )
+    }
+
+    fn to_dict(&self, py: Python) -> PyResult<Py<PyAny>> {
+        let dict = PyDict::new(py);
+        dict.set_item("rule_id", &self.rule_id)?;
+        dict.set_item("severity", &self.severity)?;
+        dict.set_item("message", &self.message)?;
+        dict.set_item("line", self.line)?;
         Ok(dict.into())
     }
 }
 
 #[pyclass(from_py_object)]
+#[doc = "Which recommended OpenContainers (OCI) image labels are set.
+
+Attributes:
+    present (list[str]): Recommended org.opencontainers.image.* labels that are set
+    missing (list[str]): Recommended org.opencontainers.image.* labels that are not set
+"]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct OciLabelReport {
+    #[pyo3(get)]
+    pub present: Vec<String>,
+    #[pyo3(get)]
+    pub missing: Vec<String>,
+}
+
+#[pymethods]
+impl OciLabelReport {
+    fn __repr__(&self) -> String {
+        format!(
+            "OciLabelReport(present={:?}, missing={:?})",
+            self.present, self.missing
+        )
+    }
+
+    fn to_dict(&self, py: Python) -> PyResult<Py<PyAny>> {
+        let dict = PyDict::new(py);
+        dict.set_item("present", &self.present)?;
+        dict.set_item("missing", &self.missing)?;
+        Ok(dict.into())
+    }
+}
+
+#[pyclass(from_py_object, eq)]
+#[doc = "A compact summary of an Analysis, for dashboards and reports.
+
+Attributes:
+    num_stages (int): Number of build stages
+    is_multistage (bool): Whether a stage builds on or copies from another
+        stage, as in MultistageAnalysis.is_multistage
+    num_images (int): Number of entries in Analysis.images
+    num_exposed_ports (int): Number of entries in Analysis.exposed_ports
+    num_findings (int): Number of lint findings
+    quality_score (int): The quality score from 0 to 100
+"]
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub struct Summary {
+    #[pyo3(get)]
+    pub num_stages: usize,
+    #[pyo3(get)]
+    pub is_multistage: bool,
+    #[pyo3(get)]
+    pub num_images: usize,
+    #[pyo3(get)]
+    pub num_exposed_ports: usize,
+    #[pyo3(get)]
+    pub num_findings: usize,
+    #[pyo3(get)]
+    pub quality_score: u8,
+}
+
+#[pymethods]
+impl Summary {
+    fn __hash__(&self) -> u64 {
+        hash_value(self)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Summary(num_stages={}, is_multistage={}, num_images={}, num_exposed_ports={}, num_findings={}, quality_score={})",
+            self.num_stages,
+            self.is_multistage,
+            self.num_images,
+            self.num_exposed_ports,
+            self.num_findings,
+            self.quality_score
+        )
+    }
+
+    fn to_dict(&self, py: Python) -> PyResult<Py<PyAny>> {
+        let dict = PyDict::new(py);
+        dict.set_item("num_stages", self.num_stages)?;
+        dict.set_item("is_multistage", self.is_multistage)?;
+        dict.set_item("num_images", self.num_images)?;
+        dict.set_item("num_exposed_ports", self.num_exposed_ports)?;
+        dict.set_item("num_findings", self.num_findings)?;
+        dict.set_item("quality_score", self.quality_score)?;
+        Ok(dict.into())
+    }
+}
+
+#[pyclass(from_py_object, eq, module = "dockerfile_analyzer")]
 #[doc = "Represents comprehensive analysis results of a Dockerfile.
 
 This class contains all the extracted information from a Dockerfile including
 stages, images, instructions, environment variables, and multistage analysis.
+
+quality_score starts at 100 and loses 20 points per error finding, 10 per
+warning and 2 per info, down to 0; quality_deductions gives the points lost
+to each lint rule.
 "]
-#[derive(Debug, PartialEq, Clone, Serialize)]
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Analysis {
     #[pyo3(get)]
     pub num_stages: usize,
     #[pyo3(get)]
+    pub escape_char: char,
+    #[pyo3(get)]
+    pub syntax_frontend: Option<String>,
+    #[pyo3(get)]
+    pub skipped_checks: Vec<String>,
+    #[pyo3(get)]
+    pub is_windows: bool,
+    #[pyo3(get)]
+    pub final_base_chain: Vec<String>,
+    #[pyo3(get)]
     pub images: Vec<Image>,
     #[pyo3(get)]
+    pub distinct_base_images: usize,
+    #[pyo3(get)]
+    pub reused_base_images: Vec<String>,
+    #[pyo3(get)]
+    pub digest_pinned_images: Vec<String>,
+    #[pyo3(get)]
+    pub registries: Vec<String>,
+    #[pyo3(get)]
     pub stage_names: Vec<String>,
     #[pyo3(get)]
+    pub removable_stages: Vec<String>,
+    #[pyo3(get)]
+    pub stages: Vec<StageAnalysis>,
+    #[pyo3(get)]
     pub copy_from_stages: Vec<String>,
     #[pyo3(get)]
+    pub copy_from_images: Vec<Image>,
+    #[pyo3(get)]
     pub add_from_stages: Vec<String>,
     #[pyo3(get)]
     pub multistage_analysis: MultistageAnalysis,
     #[pyo3(get)]
     pub exposed_ports: Vec<String>,
     #[pyo3(get)]
+    pub parsed_ports: Vec<ExposedPort>,
+    #[pyo3(get)]
     pub instructions: InstructionStats,
     #[pyo3(get)]
+    pub flags_used: HashMap<String, u32>,
+    #[pyo3(get)]
+    pub file_metrics: FileMetrics,
+    #[pyo3(get)]
+    pub per_stage_distinct_instruction_kinds: Vec<usize>,
+    #[pyo3(get)]
+    pub ordered_instructions: Vec<InstructionEntry>,
+    #[pyo3(get)]
+    pub copy_operations: Vec<CopyOperation>,
+    #[pyo3(get)]
+    pub copy_optimization_suggestions: Vec<String>,
+    #[pyo3(get)]
+    pub run_operations: Vec<RunOperation>,
+    #[pyo3(get)]
+    pub has_offline_runs: bool,
+    #[pyo3(get)]
+    pub uses_copy_link: bool,
+    #[pyo3(get)]
+    pub created_users: Vec<String>,
+    #[pyo3(get)]
     pub args: HashMap<String, Option<String>>,
     #[pyo3(get)]
+    pub global_args: HashMap<String, Option<String>>,
+    #[pyo3(get)]
+    pub arg_usages: HashMap<String, Vec<String>>,
+    #[pyo3(get)]
+    pub required_build_args: Vec<String>,
+    #[pyo3(get)]
     pub labels: HashMap<String, String>,
     #[pyo3(get)]
     pub env_vars: HashMap<String, String>,
+    #[pyo3(get)]
+    pub env_count: usize,
+    #[pyo3(get)]
+    pub arg_count: usize,
+    #[pyo3(get)]
+    pub label_count: usize,
+    #[pyo3(get)]
+    pub effective_env: HashMap<String, String>,
+    #[pyo3(get)]
+    pub path_modifications: Vec<String>,
+    #[pyo3(get)]
+    pub stop_signal: Option<String>,
+    #[pyo3(get)]
+    pub maintainer: Option<String>,
+    #[pyo3(get)]
+    pub findings: Vec<Finding>,
+    #[pyo3(get)]
+    pub quality_score: u8,
+    #[pyo3(get)]
+    pub quality_deductions: HashMap<String, u32>,
 }
 
 #[pymethods]
 impl Analysis {
+    // Hashes a subset of fields: equal analyses still hash equally, and the
+    // map-valued fields would otherwise need sorting on every call.
+    fn __hash__(&self) -> u64 {
+        hash_value(&(
+            self.num_stages,
+            &self.images,
+            &self.stage_names,
+            &self.multistage_analysis,
+            &self.exposed_ports,
+        ))
+    }
+
+    /// Serializes the analysis to a JSON string that analysis_from_json
+    /// can turn back into an equal Analysis.
+    pub fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string(self).map_err(|e| {
+            pyo3::exceptions::PyValueError::new_err(format!("Failed to serialize Analysis: {e}"))
+        })
+    }
+
+    // Pickle support: the state is the JSON serialization of the analysis,
+    // which carries every nested class along with it.
+    fn __getstate__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        Ok(PyBytes::new(py, self.to_json()?.as_bytes()))
+    }
+
+    fn __setstate__(&mut self, state: &[u8]) -> PyResult<()> {
+        *self = Self::from_state(state)?;
+        Ok(())
+    }
+
+    fn __reduce__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> PyResult<(Bound<'py, PyAny>, (Bound<'py, PyBytes>,))> {
+        let restore = slf.get_type().getattr("_from_state")?;
+        Ok((restore, (slf.borrow().__getstate__(slf.py())?,)))
+    }
+
+    /// Returns stage indices ordered so that every stage comes after the
+    /// stages it depends on. Raises ValueError if the stages form a cycle.
+    fn build_order(&self) -> PyResult<Vec<usize>> {
+        self.stage_build_order()
+            .map_err(pyo3::exceptions::PyValueError::new_err)
+    }
+
+    /// Returns the entries of `images` that are not stage names.
+    pub fn external_images(&self) -> Vec<Image> {
+        self.images
+            .iter()
+            .filter(|image| !self.is_stage_reference(image))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the entries of `images` that refer to earlier stages, such
+    /// as 'base' in 'FROM base'.
+    pub fn stage_base_references(&self) -> Vec<Image> {
+        self.images
+            .iter()
+            .filter(|image| self.is_stage_reference(image))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the distinct exposed port numbers in ascending order,
+    /// ignoring the protocol.
+    pub fn port_numbers(&self) -> Vec<u16> {
+        self.parsed_ports
+            .iter()
+            .map(|p| p.port)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Returns the distinct protocols of the exposed ports, such as "tcp"
+    /// and "udp", in alphabetical order.
+    pub fn exposed_protocols(&self) -> Vec<String> {
+        self.parsed_ports
+            .iter()
+            .map(|p| p.protocol.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Reports which recommended OCI labels are present in `labels`.
+    pub fn oci_label_report(&self) -> OciLabelReport {
+        let (present, missing): (Vec<&str>, Vec<&str>) = constants::OCI_RECOMMENDED_LABELS
+            .iter()
+            .partition(|label| self.labels.contains_key(**label));
+        OciLabelReport {
+            present: present.into_iter().map(String::from).collect(),
+            missing: missing.into_iter().map(String::from).collect(),
+        }
+    }
+
+    /// Returns the headline counts of the analysis.
+    pub fn summary(&self) -> Summary {
+        Summary {
+            num_stages: self.num_stages,
+            is_multistage: self.multistage_analysis.is_multistage,
+            num_images: self.images.len(),
+            num_exposed_ports: self.exposed_ports.len(),
+            num_findings: self.findings.len(),
+            quality_score: self.quality_score,
+        }
+    }
+
+    /// Renders the stage graph in Graphviz DOT format, with an edge from
+    /// each stage to the stages that build on it or copy from it.
+    pub fn to_dot(&self) -> String {
+        let mut stage_indices: HashMap<&str, usize> = HashMap::new();
+        for stage in &self.stages {
+            if let Some(name) = &stage.name {
+                stage_indices.entry(name.as_str()).or_insert(stage.index);
+            }
+        }
+
+        let mut edges: BTreeSet<(usize, usize, &str)> = BTreeSet::new();
+        for stage in &self.stages {
+            if let Some(&dep) = stage_indices.get(stage.base_image.as_str())
+                && dep != stage.index
+            {
+                edges.insert((dep, stage.index, "base"));
+            }
+        }
+        for op in &self.copy_operations {
+            let Some(from) = &op.from_source else {
+                continue;
+            };
+            if let Some(&dep) = stage_indices.get(from.to_lowercase().as_str()) {
+                let kind = match op.instruction.as_str() {
+                    constants::ADD => "add",
+                    _ => "copy",
+                };
+                edges.insert((dep, op.stage_index, kind));
+            }
+        }
+
+        let mut dot = String::from("digraph stages {\n");
+        for stage in &self.stages {
+            let label = match &stage.name {
+                Some(name) => name.clone(),
+                None => format!("stage {}", stage.index),
+            };
+            dot.push_str(&format!("    stage{} [label={:?}];\n", stage.index, label));
+        }
+        for (from, to, kind) in edges {
+            dot.push_str(&format!(
+                "    stage{from} -> stage{to} [label=\"{kind}\"];\n"
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Renders a plain-text report of the stages, images, exposed ports and
+    /// findings, meant for printing to a terminal.
+    pub fn to_report(&self) -> String {
+        let mut report = String::from("Dockerfile analysis\n");
+
+        report.push_str(&format!("\nStages ({})\n", self.stages.len()));
+        for stage in &self.stages {
+            let name = stage.name.as_deref().unwrap_or("-");
+            report.push_str(&format!(
+                "  {}: {} FROM {}\n",
+                stage.index, name, stage.base_image
+            ));
+        }
+
+        report.push_str(&format!("\nImages ({})\n", self.images.len()));
+        for image in &self.images {
+            report.push_str(&format!("  {}\n", image.full));
+        }
+        if self.images.is_empty() {
+            report.push_str("  (none)\n");
+        }
+
+        report.push_str(&format!("\nExposed ports ({})\n", self.exposed_ports.len()));
+        for port in &self.exposed_ports {
+            report.push_str(&format!("  {port}\n"));
+        }
+        if self.exposed_ports.is_empty() {
+            report.push_str("  (none)\n");
+        }
+
+        report.push_str(&format!(
+            "\nFindings ({}, quality score {}/100)\n",
+            self.findings.len(),
+            self.quality_score
+        ));
+        for finding in &self.findings {
+            let line = finding
+                .line
+                .map_or(String::new(), |line| format!(" line {line}"));
+            report.push_str(&format!(
+                "  [{}] {}{}: {}\n",
+                finding.severity, finding.rule_id, line, finding.message
+            ));
+        }
+        if self.findings.is_empty() {
+            report.push_str("  (none)\n");
+        }
+
+        report
+    }
+
+    fn __str__(&self) -> String {
+        self.to_report()
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        Self::from_state(state)
+    }
+
     fn __repr__(&self) -> String {
         let images_repr: Vec<String> = self.images.iter().map(|img| img.__repr__()).collect();
+        let copy_from_images_repr: Vec<String> = self
+            .copy_from_images
+            .iter()
+            .map(|img| img.__repr__())
+            .collect();
+        let stages_repr: Vec<String> = self.stages.iter().map(|st| st.__repr__()).collect();
+        let ordered_instructions_repr: Vec<String> = self
+            .ordered_instructions
+            .iter()
+            .map(|ins| ins.__repr__())
+            .collect();
+        let copy_operations_repr: Vec<String> = self
+            .copy_operations
+            .iter()
+            .map(|op| op.__repr__())
+            .collect();
+        let run_operations_repr: Vec<String> =
+            self.run_operations.iter().map(|op| op.__repr__()).collect();
+        let parsed_ports_repr: Vec<String> =
+            self.parsed_ports.iter().map(|p| p.__repr__()).collect();
+        let findings_repr: Vec<String> = self.findings.iter().map(|f| f.__repr__()).collect();
 
         format!(
-            "Analysis(num_stages={}, images=[{}], stage_names={:?}, copy_from_stages={:?}, add_from_stages={:?}, multistage_analysis={}, exposed_ports={:?}, instructions={}, args={:?}, labels={:?}, env_vars={:?})",
+            "Analysis(num_stages={}, escape_char={:?}, syntax_frontend={:?}, skipped_checks={:?}, is_windows={}, final_base_chain={:?}, images=[{}], distinct_base_images={}, reused_base_images={:?}, digest_pinned_images={:?}, registries={:?}, stage_names={:?}, removable_stages={:?}, stages=[{}], copy_from_stages={:?}, copy_from_images=[{}], add_from_stages={:?}, multistage_analysis={}, exposed_ports={:?}, parsed_ports=[{}], instructions={}, flags_used={:?}, file_metrics={}, per_stage_distinct_instruction_kinds={:?}, ordered_instructions=[{}], copy_operations=[{}], copy_optimization_suggestions={:?}, run_operations=[{}], has_offline_runs={}, uses_copy_link={}, created_users={:?}, args={:?}, global_args={:?}, arg_usages={:?}, required_build_args={:?}, labels={:?}, env_vars={:?}, env_count={}, arg_count={}, label_count={}, effective_env={:?}, path_modifications={:?}, stop_signal={:?}, maintainer={:?}, findings=[{}], quality_score={}, quality_deductions={:?})",
             self.num_stages,
+            self.escape_char,
+            self.syntax_frontend,
+            self.skipped_checks,
+            self.is_windows,
+            self.final_base_chain,
             images_repr.join(", "),
+            self.distinct_base_images,
+            self.reused_base_images,
+            self.digest_pinned_images,
+            self.registries,
             self.stage_names,
+            self.removable_stages,
+            stages_repr.join(", "),
             self.copy_from_stages,
+            copy_from_images_repr.join(", "),
             self.add_from_stages,
             self.multistage_analysis.__repr__(),
             self.exposed_ports,
+            parsed_ports_repr.join(", "),
             self.instructions.__repr__(),
+            self.flags_used,
+            self.file_metrics.__repr__(),
+            self.per_stage_distinct_instruction_kinds,
+            ordered_instructions_repr.join(", "),
+            copy_operations_repr.join(", "),
+            self.copy_optimization_suggestions,
+            run_operations_repr.join(", "),
+            self.has_offline_runs,
+            self.uses_copy_link,
+            self.created_users,
             self.args,
+            self.global_args,
+            self.arg_usages,
+            self.required_build_args,
             self.labels,
-            self.env_vars
+            self.env_vars,
+            self.env_count,
+            self.arg_count,
+            self.label_count,
+            self.effective_env,
+            self.path_modifications,
+            self.stop_signal,
+            self.maintainer,
+            findings_repr.join(", "),
+            self.quality_score,
+            self.quality_deductions
         )
     }
 
     fn to_dict(&self, py: Python) -> PyResult<Py<PyAny>> {
         let dict = PyDict::new(py);
         dict.set_item("num_stages", self.num_stages)?;
+        dict.set_item("escape_char", self.escape_char)?;
+        dict.set_item("syntax_frontend", &self.syntax_frontend)?;
+        dict.set_item("skipped_checks", &self.skipped_checks)?;
+        dict.set_item("is_windows", self.is_windows)?;
+        dict.set_item("final_base_chain", &self.final_base_chain)?;
 
         // Convert Vec<Image> to Vec<PyObject>
         let images: PyResult<Vec<Py<PyAny>>> =
             self.images.iter().map(|img| img.to_dict(py)).collect();
         dict.set_item("images", images?)?;
+        dict.set_item("distinct_base_images", self.distinct_base_images)?;
+        dict.set_item("reused_base_images", &self.reused_base_images)?;
+        dict.set_item("digest_pinned_images", &self.digest_pinned_images)?;
+        dict.set_item("registries", &self.registries)?;
 
         dict.set_item("stage_names", &self.stage_names)?;
+        dict.set_item("removable_stages", &self.removable_stages)?;
+
+        let stages: PyResult<Vec<Py<PyAny>>> =
+            self.stages.iter().map(|st| st.to_dict(py)).collect();
+        dict.set_item("stages", stages?)?;
+
         dict.set_item("copy_from_stages", &self.copy_from_stages)?;
+        let copy_from_images: PyResult<Vec<Py<PyAny>>> = self
+            .copy_from_images
+            .iter()
+            .map(|img| img.to_dict(py))
+            .collect();
+        dict.set_item("copy_from_images", copy_from_images?)?;
         dict.set_item("add_from_stages", &self.add_from_stages)?;
         dict.set_item("multistage_analysis", self.multistage_analysis.to_dict(py)?)?;
         dict.set_item("exposed_ports", &self.exposed_ports)?;
+
+        let parsed_ports: PyResult<Vec<Py<PyAny>>> =
+            self.parsed_ports.iter().map(|p| p.to_dict(py)).collect();
+        dict.set_item("parsed_ports", parsed_ports?)?;
+
         dict.set_item("instructions", self.instructions.to_dict(py)?)?;
+        dict.set_item("flags_used", &self.flags_used)?;
+        dict.set_item("file_metrics", self.file_metrics.to_dict(py)?)?;
+        dict.set_item(
+            "per_stage_distinct_instruction_kinds",
+            &self.per_stage_distinct_instruction_kinds,
+        )?;
+
+        let ordered_instructions: PyResult<Vec<Py<PyAny>>> = self
+            .ordered_instructions
+            .iter()
+            .map(|ins| ins.to_dict(py))
+            .collect();
+        dict.set_item("ordered_instructions", ordered_instructions?)?;
+
+        let copy_operations: PyResult<Vec<Py<PyAny>>> = self
+            .copy_operations
+            .iter()
+            .map(|op| op.to_dict(py))
+            .collect();
+        dict.set_item("copy_operations", copy_operations?)?;
+        dict.set_item(
+            "copy_optimization_suggestions",
+            &self.copy_optimization_suggestions,
+        )?;
+
+        let run_operations: PyResult<Vec<Py<PyAny>>> = self
+            .run_operations
+            .iter()
+            .map(|op| op.to_dict(py))
+            .collect();
+        dict.set_item("run_operations", run_operations?)?;
+        dict.set_item("has_offline_runs", self.has_offline_runs)?;
+        dict.set_item("uses_copy_link", self.uses_copy_link)?;
+        dict.set_item("created_users", &self.created_users)?;
+
         dict.set_item("args", &self.args)?;
+        dict.set_item("global_args", &self.global_args)?;
+        dict.set_item("arg_usages", &self.arg_usages)?;
+        dict.set_item("required_build_args", &self.required_build_args)?;
         dict.set_item("labels", &self.labels)?;
         dict.set_item("env_vars", &self.env_vars)?;
+        dict.set_item("env_count", self.env_count)?;
+        dict.set_item("arg_count", self.arg_count)?;
+        dict.set_item("label_count", self.label_count)?;
+        dict.set_item("effective_env", &self.effective_env)?;
+        dict.set_item("path_modifications", &self.path_modifications)?;
+        dict.set_item("stop_signal", &self.stop_signal)?;
+        dict.set_item("maintainer", &self.maintainer)?;
+
+        let findings: PyResult<Vec<Py<PyAny>>> =
+            self.findings.iter().map(|f| f.to_dict(py)).collect();
+        dict.set_item("findings", findings?)?;
+        dict.set_item("quality_score", self.quality_score)?;
+        dict.set_item("quality_deductions", &self.quality_deductions)?;
         Ok(dict.into())
     }
 }
 
+impl Analysis {
+    // Kahn's algorithm, always taking the lowest ready index so the order
+    // matches file order wherever dependencies allow.
+    pub fn stage_build_order(&self) -> Result<Vec<usize>, String> {
+        let mut remaining: Vec<usize> = self.stages.iter().map(|s| s.depends_on.len()).collect();
+        let mut ready: BTreeSet<usize> = (0..self.stages.len())
+            .filter(|&i| remaining[i] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.stages.len());
+
+        while let Some(index) = ready.pop_first() {
+            order.push(index);
+            for stage in &self.stages {
+                if stage.depends_on.contains(&index) {
+                    remaining[stage.index] -= 1;
+                    if remaining[stage.index] == 0 {
+                        ready.insert(stage.index);
+                    }
+                }
+            }
+        }
+
+        if order.len() != self.stages.len() {
+            let cyclic: Vec<usize> = (0..self.stages.len())
+                .filter(|i| !order.contains(i))
+                .collect();
+            return Err(format!("Stage dependency cycle between stages {cyclic:?}"));
+        }
+        Ok(order)
+    }
+
+    // Stage names are matched case-insensitively, as Docker does, so the
+    // result does not depend on preserve_stage_case.
+    fn is_stage_reference(&self, image: &Image) -> bool {
+        self.stage_names
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(&image.full))
+    }
+
+    fn from_state(state: &[u8]) -> PyResult<Self> {
+        serde_json::from_slice(state).map_err(|e| {
+            pyo3::exceptions::PyValueError::new_err(format!("Invalid Analysis state: {e}"))
+        })
+    }
+
+    pub fn from_json(json: &str) -> PyResult<Self> {
+        serde_json::from_str(json).map_err(|e| {
+            pyo3::exceptions::PyValueError::new_err(format!("Invalid Analysis JSON: {e}"))
+        })
+    }
+}
+
 #[pyclass(from_py_object)]
 #[doc = "Key/Value Pairs found in ARG, ENV, and LABEL instructions.
 "]
-#[derive(Debug, PartialEq, Clone, Serialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct KeyValueInstr {
     #[pyo3(get)]
     pub args: HashMap<String, Option<String>>,
@@ -267,3 +1492,93 @@ impl KeyValueInstr {
         Ok(dict.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Analysis, ImageComponents};
+    use crate::analyzer::analyze_dockerfile;
+    use pyo3::prelude::*;
+
+    #[test]
+    fn test_python_eq_and_hash() {
+        let dockerfile = "FROM node:20-alpine AS builder\nRUN npm ci\nFROM nginx:alpine\nCOPY --from=builder /app/dist /usr/share/nginx/html\nEXPOSE 80\n";
+        Python::initialize();
+        Python::attach(|py| {
+            let first = Py::new(py, analyze_dockerfile(dockerfile).unwrap()).unwrap();
+            let second = Py::new(py, analyze_dockerfile(dockerfile).unwrap()).unwrap();
+            let other = Py::new(py, analyze_dockerfile("FROM alpine:3.18\n").unwrap()).unwrap();
+            let (first, second, other) = (first.bind(py), second.bind(py), other.bind(py));
+
+            assert!(first.eq(second).unwrap());
+            assert!(first.ne(other).unwrap());
+            assert_eq!(first.hash().unwrap(), second.hash().unwrap());
+
+            for attr in ["instructions", "multistage_analysis"] {
+                let a = first.getattr(attr).unwrap();
+                let b = second.getattr(attr).unwrap();
+                assert!(a.eq(&b).unwrap());
+                assert_eq!(a.hash().unwrap(), b.hash().unwrap());
+            }
+
+            let image = first.getattr("images").unwrap().get_item(0).unwrap();
+            let same_image = second.getattr("images").unwrap().get_item(0).unwrap();
+            assert_eq!(image.hash().unwrap(), same_image.hash().unwrap());
+            let components = image.getattr("components").unwrap();
+            let same_components = same_image.getattr("components").unwrap();
+            assert!(components.eq(&same_components).unwrap());
+            assert_eq!(components.hash().unwrap(), same_components.hash().unwrap());
+        });
+    }
+
+    #[test]
+    fn test_digest_algorithm() {
+        let components = ImageComponents {
+            registry: None,
+            name: "python".to_string(),
+            tag: Some("3.13".to_string()),
+            digest: Some(
+                "sha256:55f1d15ef4c37870e23c03e89ad238940b55c8ede9f13fac4b7d71c7955f1053"
+                    .to_string(),
+            ),
+        };
+        assert_eq!(components.digest_algorithm(), Some("sha256"));
+        let components = ImageComponents {
+            digest: None,
+            ..components
+        };
+        assert_eq!(components.digest_algorithm(), None);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let dockerfile = "FROM golang:1.22 AS build\nARG TOKEN\nCOPY . /src\nRUN go build -o /app\nFROM scratch\nCOPY --from=build /app /app\nEXPOSE 8080-8081/udp\nENTRYPOINT [\"/app\"]\n";
+        let analysis = analyze_dockerfile(dockerfile).unwrap();
+        let json = analysis.to_json().unwrap();
+        assert_eq!(Analysis::from_json(&json).unwrap(), analysis);
+        assert!(Analysis::from_json("{\"num_stages\": 1}").is_err());
+    }
+
+    #[test]
+    fn test_pickle_round_trip() {
+        let dockerfile = "ARG VERSION=3.18\nFROM alpine:${VERSION} AS base\nENV PATH=/opt/bin\nLABEL version=\"1.0\"\nFROM base\nCOPY --from=base a b /dest/\nEXPOSE 80/tcp\n";
+        Python::initialize();
+        Python::attach(|py| {
+            let module = pyo3::wrap_pymodule!(crate::dockerfile_analyzer)(py);
+            py.import("sys")
+                .unwrap()
+                .getattr("modules")
+                .unwrap()
+                .set_item("dockerfile_analyzer", module)
+                .unwrap();
+
+            let analysis = analyze_dockerfile(dockerfile).unwrap();
+            let original = Py::new(py, analysis.clone()).unwrap();
+            let pickle = py.import("pickle").unwrap();
+            let data = pickle.call_method1("dumps", (&original,)).unwrap();
+            let restored = pickle.call_method1("loads", (data,)).unwrap();
+
+            assert!(restored.eq(original.bind(py)).unwrap());
+            assert_eq!(restored.extract::<Analysis>().unwrap(), analysis);
+        });
+    }
+}
@@ -1,4 +1,6 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 
 const ARG_LC: &str = "arg";
 const ENV_LC: &str = "env";
@@ -15,6 +17,299 @@ pub fn parse_kv_instruction_opt_val(ins: &str) -> HashMap<String, Option<String>
     vec_to_map_opt_val(&toks)
 }
 
+/// Returns the keys of a key-value instruction in the order given, keeping
+/// repeated keys that `parse_kv_instruction` collapses.
+pub fn kv_instruction_keys(ins: &str) -> Vec<String> {
+    extract_tokens_from_instr(ins)
+        .into_iter()
+        .step_by(2)
+        .collect()
+}
+
+/// Returns the check names listed by the skip key of a `# check=` parser
+/// directive, e.g. `skip=JSONArgsRecommended,StageNameCasing;error=true`.
+pub fn check_directive_skips(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .filter_map(|part| {
+            let (key, checks) = part.split_once('=')?;
+            key.trim().eq_ignore_ascii_case("skip").then_some(checks)
+        })
+        .flat_map(|checks| checks.split(','))
+        .map(str::trim)
+        .filter(|check| !check.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Strips a leading UTF-8 byte order mark, as written by some Windows editors.
+pub fn strip_bom(body: &str) -> &str {
+    body.strip_prefix('\u{feff}').unwrap_or(body)
+}
+
+/// Strips a leading byte order mark and converts CRLF line endings to LF, so
+/// offsets, line numbers and tokens match those of the LF equivalent.
+pub fn normalize_line_endings(body: &str) -> Cow<'_, str> {
+    let body = strip_bom(body);
+    match body.contains('\r') {
+        true => Cow::Owned(body.replace("\r\n", "\n")),
+        false => Cow::Borrowed(body),
+    }
+}
+
+/// Returns the one-based line number containing the byte `offset` of `body`.
+pub fn line_number(body: &str, offset: usize) -> usize {
+    body[..offset].matches('\n').count() + 1
+}
+
+/// Returns the contiguous block of comment lines directly above the one-based
+/// `line`, with the leading `#` and surrounding whitespace removed.
+pub fn preceding_comments(body: &str, line: usize) -> Vec<&str> {
+    let mut comments: Vec<&str> = body
+        .lines()
+        .take(line.saturating_sub(1))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .map(str::trim)
+        .take_while(|l| l.starts_with('#'))
+        .map(|l| l.trim_start_matches('#').trim())
+        .collect();
+    comments.reverse();
+    comments
+}
+
+/// Returns true if `text` references the variable `name` as `$name` or
+/// `${name}` (including modifiers such as `${name:-default}`).
+pub fn references_var(text: &str, name: &str) -> bool {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    text.match_indices('$').any(|(i, _)| {
+        let rest = &text[i + 1..];
+        let rest = rest.strip_prefix('{').unwrap_or(rest);
+        rest.strip_prefix(name)
+            .is_some_and(|after| !after.starts_with(is_ident))
+    })
+}
+
+/// Splits an EXPOSE value such as `8080/udp` into the port part and the
+/// lowercased protocol, which defaults to `tcp`.
+pub fn split_port_protocol(spec: &str) -> (&str, String) {
+    match spec.split_once('/') {
+        Some((ports, protocol)) => (ports, protocol.to_lowercase()),
+        None => (spec, "tcp".to_string()),
+    }
+}
+
+/// Parses the port part of an EXPOSE value, either a single port or a
+/// `start-end` range. Returns None if a bound is not a valid port number;
+/// a reversed range comes back empty.
+pub fn parse_port_range(ports: &str) -> Option<RangeInclusive<u16>> {
+    match ports.split_once('-') {
+        Some((start, end)) => Some(start.trim().parse().ok()?..=end.trim().parse().ok()?),
+        None => {
+            let port = ports.trim().parse().ok()?;
+            Some(port..=port)
+        }
+    }
+}
+
+/// Splits a shell command into pipelines on `&&`, `||` and `;`, and each
+/// pipeline into its piped stages. Line continuations using the `escape`
+/// character are joined first. Quoting is not taken into account.
+pub fn run_pipelines(command: &str, escape: char) -> Vec<Vec<String>> {
+    let joined = command
+        .replace(&format!("{escape}\r\n"), " ")
+        .replace(&format!("{escape}\n"), " ");
+    joined
+        .split("&&")
+        .flat_map(|part| part.split("||"))
+        .flat_map(|part| part.split(';'))
+        .map(|pipeline| {
+            pipeline
+                .split('|')
+                .map(|stage| stage.trim().to_string())
+                .filter(|stage| !stage.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .filter(|pipeline| !pipeline.is_empty())
+        .collect()
+}
+
+/// Splits a pipeline stage into shell words, falling back to whitespace when
+/// the quoting is unbalanced.
+pub fn stage_tokens(stage: &str) -> Vec<String> {
+    shlex::split(stage).unwrap_or_else(|| stage.split_whitespace().map(String::from).collect())
+}
+
+/// Returns the program run by a pipeline stage, looking past `sudo`.
+pub fn stage_program(tokens: &[String]) -> Option<&str> {
+    tokens
+        .iter()
+        .map(String::as_str)
+        .find(|t| *t != "sudo")
+        .map(|t| t.rsplit('/').next().unwrap_or(t))
+}
+
+/// Resolves a `--chmod` value to its octal mode. Octal modes such as `0644`
+/// are taken as is. Symbolic modes such as `u=rwx,go=rx` or `a+rwx` only
+/// resolve when they determine every permission bit, since the mode of the
+/// source file is unknown; `u+x` and modes using `X`, `s` or `t` give None.
+pub fn parse_chmod(mode: &str) -> Option<u32> {
+    if !mode.is_empty() && mode.chars().all(|c| c.is_ascii_digit()) {
+        return u32::from_str_radix(mode, 8).ok().filter(|m| *m <= 0o7777);
+    }
+
+    let mut value = 0u32;
+    let mut known = 0u32;
+    for clause in mode.split(',') {
+        let ops_start = clause.find(['+', '-', '='])?;
+        let (who, mut rest) = clause.split_at(ops_start);
+        let mut who_mask = 0u32;
+        for c in who.chars() {
+            who_mask |= match c {
+                'u' => 0o700,
+                'g' => 0o070,
+                'o' => 0o007,
+                'a' => 0o777,
+                _ => return None,
+            };
+        }
+        if who_mask == 0 {
+            who_mask = 0o777;
+        }
+
+        while let Some(op) = rest.chars().next() {
+            let perms_end = rest[1..]
+                .find(['+', '-', '='])
+                .map_or(rest.len(), |i| i + 1);
+            let mut perms = 0u32;
+            for c in rest[1..perms_end].chars() {
+                perms |= match c {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' => 0o111,
+                    _ => return None,
+                };
+            }
+            let bits = perms & who_mask;
+            match op {
+                '+' => {
+                    value |= bits;
+                    known |= bits;
+                }
+                '-' => {
+                    value &= !bits;
+                    known |= bits;
+                }
+                '=' => {
+                    value = (value & !who_mask) | bits;
+                    known |= who_mask;
+                }
+                _ => return None,
+            }
+            rest = &rest[perms_end..];
+        }
+    }
+
+    (known == 0o777).then_some(value)
+}
+
+/// Whether `path` is absolute, either a POSIX path or a Windows path with a
+/// drive letter such as `C:\app`.
+pub fn is_absolute_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    path.starts_with(['/', '\\'])
+        || (bytes.len() >= 3
+            && bytes[0].is_ascii_alphabetic()
+            && bytes[1] == b':'
+            && matches!(bytes[2], b'/' | b'\\'))
+}
+
+/// Resolves a WORKDIR `path` against the current working directory the way
+/// Docker does: absolute paths replace it and relative paths are appended,
+/// with `.` and `..` segments removed.
+pub fn resolve_workdir(current: &str, path: &str) -> String {
+    let joined = if is_absolute_path(path) {
+        path.to_string()
+    } else {
+        format!("{}/{}", current.trim_end_matches('/'), path)
+    };
+    if !joined.starts_with('/') {
+        return joined;
+    }
+    let mut segments: Vec<&str> = vec![];
+    for segment in joined.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+/// Whether `value` is an RFC 3339 timestamp such as `2024-05-01T12:30:00Z`
+/// or `2024-05-01T12:30:00.5+02:00`.
+pub fn is_rfc3339_timestamp(value: &str) -> bool {
+    rfc3339_fields(value).is_some()
+}
+
+fn rfc3339_fields(value: &str) -> Option<()> {
+    fn number(s: &str, len: usize, range: RangeInclusive<u32>) -> Option<u32> {
+        let n =
+            (s.len() == len && s.bytes().all(|b| b.is_ascii_digit())).then(|| s.parse().ok())??;
+        range.contains(&n).then_some(n)
+    }
+
+    let (date, time) = value.split_once(['T', 't', ' '])?;
+    let mut date = date.split('-');
+    let year = number(date.next()?, 4, 0..=9999)?;
+    let month = number(date.next()?, 2, 1..=12)?;
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    number(date.next()?, 2, 1..=days)?;
+    if date.next().is_some() {
+        return None;
+    }
+
+    let (clock, offset) = match time.strip_suffix(['Z', 'z']) {
+        Some(clock) => (clock, None),
+        None => {
+            let sign = time.rfind(['+', '-'])?;
+            (&time[..sign], Some(&time[sign + 1..]))
+        }
+    };
+    let (clock, fraction) = match clock.split_once('.') {
+        Some((clock, fraction)) => (clock, Some(fraction)),
+        None => (clock, None),
+    };
+    if fraction.is_some_and(|f| f.is_empty() || !f.bytes().all(|b| b.is_ascii_digit())) {
+        return None;
+    }
+    let mut clock = clock.split(':');
+    number(clock.next()?, 2, 0..=23)?;
+    number(clock.next()?, 2, 0..=59)?;
+    // 60 allows for leap seconds.
+    number(clock.next()?, 2, 0..=60)?;
+    if clock.next().is_some() {
+        return None;
+    }
+
+    if let Some(offset) = offset {
+        let (hours, minutes) = offset.split_once(':')?;
+        number(hours, 2, 0..=23)?;
+        number(minutes, 2, 0..=59)?;
+    }
+    Some(())
+}
+
 fn extract_tokens_from_instr(ins: &str) -> Vec<String> {
     let mut processed: Vec<String> = vec![];
 
@@ -87,6 +382,18 @@ fn vec_to_map_opt_val(toks: &[String]) -> HashMap<String, Option<String>> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_normalize_line_endings() {
+        assert_eq!(
+            normalize_line_endings("\u{feff}FROM a\r\nRUN b \\\r\n  c\r\n"),
+            "FROM a\nRUN b \\\n  c\n"
+        );
+        assert!(matches!(
+            normalize_line_endings("FROM a\n"),
+            Cow::Borrowed("FROM a\n")
+        ));
+    }
+
     #[test]
     fn test_basic_equal() {
         assert_eq!(
@@ -558,6 +865,162 @@ VAR2=value2"#;
         );
     }
 
+    #[test]
+    fn test_line_number() {
+        let body = "FROM alpine\nRUN true\n\nCMD [\"sh\"]";
+        assert_eq!(line_number(body, 0), 1);
+        assert_eq!(line_number(body, body.find("RUN").unwrap()), 2);
+        assert_eq!(line_number(body, body.find("CMD").unwrap()), 4);
+    }
+
+    #[test]
+    fn test_preceding_comments() {
+        let body = r#"
+# unrelated
+FROM alpine
+
+# ubuntu 22.04
+#   pinned by digest
+FROM ubuntu
+"#;
+        assert_eq!(
+            preceding_comments(body, 7),
+            vec!["ubuntu 22.04", "pinned by digest"]
+        );
+        assert!(preceding_comments(body, 3).contains(&"unrelated"));
+        assert!(preceding_comments(body, 1).is_empty());
+    }
+
+    #[test]
+    fn test_references_var() {
+        assert!(references_var("GIT_COMMIT=$GIT_COMMIT", "GIT_COMMIT"));
+        assert!(references_var(
+            "node:${NODE_VERSION}-alpine",
+            "NODE_VERSION"
+        ));
+        assert!(references_var("${PORT:-8080}", "PORT"));
+        assert!(!references_var("$PORT_NUMBER", "PORT"));
+        assert!(!references_var("PORT=8080", "PORT"));
+    }
+
+    #[test]
+    fn test_split_port_protocol() {
+        assert_eq!(split_port_protocol("8080"), ("8080", "tcp".to_string()));
+        assert_eq!(split_port_protocol("53/UDP"), ("53", "udp".to_string()));
+        assert_eq!(
+            split_port_protocol("3000-3005/tcp"),
+            ("3000-3005", "tcp".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_port_range() {
+        assert_eq!(parse_port_range("80"), Some(80..=80));
+        assert_eq!(parse_port_range("3000-3005"), Some(3000..=3005));
+        assert!(parse_port_range("3005-3000").unwrap().is_empty());
+        assert_eq!(parse_port_range("$PORT"), None);
+        assert_eq!(parse_port_range("3000-"), None);
+    }
+
+    #[test]
+    fn test_parse_chmod_octal() {
+        assert_eq!(parse_chmod("0644"), Some(0o644));
+        assert_eq!(parse_chmod("755"), Some(0o755));
+        assert_eq!(parse_chmod("4755"), Some(0o4755));
+        assert_eq!(parse_chmod("0999"), None);
+        assert_eq!(parse_chmod("77777"), None);
+    }
+
+    #[test]
+    fn test_parse_chmod_symbolic() {
+        assert_eq!(parse_chmod("a+rwx"), Some(0o777));
+        assert_eq!(parse_chmod("+rwx"), Some(0o777));
+        assert_eq!(parse_chmod("u=rwx,go=rx"), Some(0o755));
+        assert_eq!(parse_chmod("a=rw,o-w"), Some(0o664));
+        assert_eq!(parse_chmod("a=r,u+w"), Some(0o644));
+        assert_eq!(parse_chmod("u+x"), None);
+        assert_eq!(parse_chmod("a+X"), None);
+        assert_eq!(parse_chmod("z+r"), None);
+        assert_eq!(parse_chmod(""), None);
+    }
+
+    #[test]
+    fn test_run_pipelines_backtick_escape() {
+        let command = "apt-get update && `\r\n    apt-get install -y curl";
+        assert_eq!(
+            run_pipelines(command, '`'),
+            vec![
+                vec!["apt-get update".to_string()],
+                vec!["apt-get install -y curl".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_absolute_path() {
+        assert!(is_absolute_path("/app"));
+        assert!(is_absolute_path("C:\\app"));
+        assert!(is_absolute_path("c:/app"));
+        assert!(!is_absolute_path("app"));
+        assert!(!is_absolute_path("./app"));
+        assert!(!is_absolute_path("$HOME"));
+    }
+
+    #[test]
+    fn test_resolve_workdir() {
+        assert_eq!(resolve_workdir("/", "app"), "/app");
+        assert_eq!(resolve_workdir("/app", "src"), "/app/src");
+        assert_eq!(resolve_workdir("/app/", "./src/../lib"), "/app/lib");
+        assert_eq!(resolve_workdir("/app", "/srv"), "/srv");
+        assert_eq!(resolve_workdir("/app", ".."), "/");
+    }
+
+    #[test]
+    fn test_kv_instruction_keys() {
+        assert_eq!(
+            kv_instruction_keys("version=1.0 \"description\"=\"My app\" version=2.0"),
+            vec!["version", "description", "version"]
+        );
+    }
+
+    #[test]
+    fn test_check_directive_skips() {
+        assert_eq!(
+            check_directive_skips("skip=JSONArgsRecommended, StageNameCasing;error=true"),
+            vec!["JSONArgsRecommended", "StageNameCasing"]
+        );
+        assert_eq!(check_directive_skips("skip=all"), vec!["all"]);
+        assert!(check_directive_skips("error=true").is_empty());
+    }
+
+    #[test]
+    fn test_is_rfc3339_timestamp() {
+        assert!(is_rfc3339_timestamp("2024-05-01T12:30:00Z"));
+        assert!(is_rfc3339_timestamp("2024-02-29t23:59:60.123+02:00"));
+        assert!(is_rfc3339_timestamp("2024-05-01 12:30:00-07:00"));
+        assert!(!is_rfc3339_timestamp("2023-02-29T00:00:00Z"));
+        assert!(!is_rfc3339_timestamp("2024-05-01"));
+        assert!(!is_rfc3339_timestamp("2024-05-01T12:30Z"));
+        assert!(!is_rfc3339_timestamp("2024-05-01T12:30:00"));
+        assert!(!is_rfc3339_timestamp("2024-13-01T12:30:00Z"));
+        assert!(!is_rfc3339_timestamp("2024-05-01T12:30:00.Z"));
+        assert!(!is_rfc3339_timestamp("yesterday"));
+    }
+
+    #[test]
+    fn test_run_pipelines() {
+        let command = "apt-get update && \\\n    curl -fsSL https://example.com/x.tgz | tar xz -C /opt; echo done || true";
+        assert_eq!(
+            run_pipelines(command, '\\'),
+            vec![
+                vec!["apt-get update"],
+                vec!["curl -fsSL https://example.com/x.tgz", "tar xz -C /opt"],
+                vec!["echo done"],
+                vec!["true"],
+            ]
+        );
+    }
+
     #[test]
     fn test_embedded_dockerfile_instructions() {
         assert_eq!(